@@ -0,0 +1,127 @@
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::revocation::now;
+use crate::store::Store;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of a single push, recorded against the registration.
+pub enum Delivery {
+    Delivered,
+    /// The token is no longer valid; the registration is pruned.
+    Gone(String),
+    Failed(String),
+}
+
+impl Delivery {
+    fn status(&self) -> String {
+        match self {
+            Delivery::Delivered => "delivered".into(),
+            Delivery::Gone(reason) => format!("gone: {reason}"),
+            Delivery::Failed(reason) => format!("failed: {reason}"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ApnsError {
+    reason: String,
+}
+
+/// HTTP/2 client for pass update notifications, authenticated with the pass
+/// type certificate.
+pub struct ApnsClient {
+    client: reqwest::Client,
+    url: String,
+    topic: String,
+}
+
+impl ApnsClient {
+    /// `topic` is the pass type identifier the certificate was issued for.
+    /// Plain `http://` URLs talk HTTP/2 without TLS to support mock servers.
+    pub fn new(
+        url: &str,
+        topic: &str,
+        cert_pem: &[u8],
+        key_pem: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut builder = reqwest::Client::builder().timeout(REQUEST_TIMEOUT);
+        if url.starts_with("http://") {
+            builder = builder.http2_prior_knowledge();
+        } else {
+            builder = builder
+                .identity(reqwest::Identity::from_pkcs8_pem(cert_pem, key_pem)?)
+                .https_only(true);
+        }
+        Ok(Self {
+            client: builder.build()?,
+            url: url.trim_end_matches('/').to_string(),
+            topic: topic.to_string(),
+        })
+    }
+
+    /// Sends the empty notification that makes Wallet ask the web service
+    /// for updated passes.
+    pub async fn push(&self, push_token: &str) -> Delivery {
+        let response = match self
+            .client
+            .post(format!("{}/3/device/{push_token}", self.url))
+            .header("apns-topic", &self.topic)
+            .body("{}")
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => return Delivery::Failed(e.to_string()),
+        };
+        let status = response.status();
+        if status.is_success() {
+            return Delivery::Delivered;
+        }
+        let reason = response
+            .json::<ApnsError>()
+            .await
+            .map(|e| e.reason)
+            .unwrap_or_else(|_| status.to_string());
+        match (status.as_u16(), reason.as_str()) {
+            (410, _) | (400, "BadDeviceToken") => Delivery::Gone(reason),
+            _ => Delivery::Failed(reason),
+        }
+    }
+
+    /// Notifies every device registered for `serials`, records the results
+    /// and drops registrations whose token is gone.
+    pub async fn notify(&self, store: &Store, serials: &[String]) {
+        let registrations = match store.registrations(serials) {
+            Ok(registrations) => registrations,
+            Err(e) => {
+                warn!("Failed to load device registrations: {e}");
+                return;
+            }
+        };
+        for registration in registrations {
+            let device = &registration.device_library_identifier;
+            let serial = &registration.serial_number;
+            let delivery = self.push(&registration.push_token).await;
+            let result = match &delivery {
+                Delivery::Gone(reason) => {
+                    info!("Pruning device {device} of pass {serial}: {reason}");
+                    store.prune_push_token(&registration.push_token).map(|_| ())
+                }
+                Delivery::Failed(reason) => {
+                    warn!("Push to device {device} for pass {serial} failed: {reason}");
+                    store.record_push(device, serial, &delivery.status(), now())
+                }
+                Delivery::Delivered => {
+                    debug!("Pushed update of pass {serial} to device {device}");
+                    store.record_push(device, serial, &delivery.status(), now())
+                }
+            };
+            if let Err(e) = result {
+                warn!("Failed to record push result: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use jsonwebtoken::EncodingKey;
+use log::{error, info};
+use passes::resource::{Type, Version};
+use passes::sign::{SignConfig, WWDR};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::apns::ApnsClient;
+use crate::card::CardLogo;
+use crate::config::{AppleConfig, Config, GoogleConfig, QrConfig};
+use crate::error::Error;
+use crate::google_wallet::WalletClient;
+use crate::passes::{generic_class, gpass_class_id};
+use crate::require_admin;
+use crate::semester::Calendar;
+use crate::template::{Design, PassTemplate};
+use crate::utils::log_public_key;
+
+/// Everything needed to sign credentials, read from disk once.
+pub struct Material {
+    pub qr: QrConfig,
+    pub calendar: Calendar,
+    pub template: PassTemplate,
+    /// `None` if `resources/logo.png` is missing, which disables `/card.pdf`.
+    pub card_logo: Option<CardLogo>,
+    pub apple: Option<AppleMaterial>,
+    pub google: Option<GoogleMaterial>,
+}
+
+pub struct AppleMaterial {
+    pub config: AppleConfig,
+    sign_config: SignConfig,
+    /// Images added to the pass, with their resource type.
+    images: Vec<(Type, Vec<u8>)>,
+    /// Images of designs with their own image directory, by design name.
+    design_images: HashMap<String, Vec<(Type, Vec<u8>)>>,
+    /// Only set if passes are updatable, i.e. `PKPASS_WEB_SERVICE_URL` is set.
+    pub apns: Option<ApnsClient>,
+}
+
+impl AppleMaterial {
+    /// The images of a pass in `design`.
+    pub fn images(&self, design: &Design) -> &[(Type, Vec<u8>)] {
+        self.design_images.get(&design.name).unwrap_or(&self.images)
+    }
+
+    /// A copy of the parsed signing certificate and key for one package.
+    pub fn sign_config(&self) -> SignConfig {
+        SignConfig {
+            cert: self.sign_config.cert.clone(),
+            sign_cert: self.sign_config.sign_cert.clone(),
+            sign_key: self.sign_config.sign_key.clone(),
+        }
+    }
+}
+
+pub struct GoogleMaterial {
+    pub config: GoogleConfig,
+    pub encoding_key: EncodingKey,
+    pub api: WalletClient,
+}
+
+impl Material {
+    /// Reads the certificates, keys and images referenced by `config`.
+    pub fn load(config: Config) -> Result<Self, Error> {
+        let apple = match config.apple {
+            Some(apple) => {
+                let sign_cert = read(&apple.sign_cert_path)?;
+                let sign_key = read(&apple.sign_key_path)?;
+                let sign_config =
+                    SignConfig::new(WWDR::G4, &sign_cert, &sign_key).map_err(|e| {
+                        Error::Internal(format!("invalid Apple signing certificate or key: {e}"))
+                    })?;
+                let mut images = Vec::new();
+                for (suffix, version) in [
+                    ("", Version::Standard),
+                    ("@2x", Version::Size2X),
+                    ("@3x", Version::Size3X),
+                ] {
+                    let path =
+                        |name: &str| config.resources_dir.join(format!("{name}{suffix}.png"));
+                    images.push((Type::Icon(version.clone()), read(path("icon"))?));
+                    images.push((Type::Logo(version), read(path("logo"))?));
+                }
+                let mut design_images = HashMap::new();
+                for design in &config.template.designs {
+                    if let Some(dir) = &design.apple.images {
+                        let dir = config.resources_dir.join(dir);
+                        design_images.insert(design.name.clone(), design_images_in(&dir, &images)?);
+                    }
+                }
+                let apns = match apple.web_service_url {
+                    Some(_) => {
+                        let apns_error = |e: &dyn Display| {
+                            Error::Internal(format!("failed to set up APNs client: {e}"))
+                        };
+                        let cert = sign_config.sign_cert.to_pem().map_err(|e| apns_error(&e))?;
+                        let key = sign_config
+                            .sign_key
+                            .private_key_to_pem_pkcs8()
+                            .map_err(|e| apns_error(&e))?;
+                        Some(
+                            ApnsClient::new(
+                                &apple.apns_url,
+                                &apple.pass_type_identifier,
+                                &cert,
+                                &key,
+                            )
+                            .map_err(|e| apns_error(&e))?,
+                        )
+                    }
+                    None => None,
+                };
+                Some(AppleMaterial {
+                    config: apple,
+                    sign_config,
+                    images,
+                    design_images,
+                    apns,
+                })
+            }
+            None => None,
+        };
+
+        let google = match config.google {
+            Some(google) => {
+                let private_key_pem = read(&google.service_account_key_path)?;
+                let encoding_key = EncodingKey::from_rsa_pem(&private_key_pem).map_err(|e| {
+                    Error::Internal(format!("invalid Google service account key: {e}"))
+                })?;
+                let mut classes = HashMap::new();
+                for design in config.template.designs() {
+                    let class_id = gpass_class_id(&google, design);
+                    let class = generic_class(&config.template.google, &class_id)?;
+                    classes.insert(class_id, class);
+                }
+                let api =
+                    WalletClient::new(&google, encoding_key.clone(), classes).map_err(|e| {
+                        Error::Internal(format!("failed to set up Google Wallet client: {e}"))
+                    })?;
+                Some(GoogleMaterial {
+                    config: google,
+                    encoding_key,
+                    api,
+                })
+            }
+            None => None,
+        };
+
+        let card_logo_path = config.resources_dir.join("logo.png");
+        let card_logo = if card_logo_path.is_file() {
+            Some(CardLogo::decode(&read(&card_logo_path)?).map_err(Error::Internal)?)
+        } else {
+            info!(
+                "{} not found, printable cards are disabled",
+                card_logo_path.display()
+            );
+            None
+        };
+
+        Ok(Material {
+            qr: config.qr,
+            calendar: config.calendar,
+            template: config.template,
+            card_logo,
+            apple,
+            google,
+        })
+    }
+}
+
+/// `images` with those in `dir` replacing or adding to them; files are
+/// named like the pass resources, e.g. `strip@2x.png`.
+fn design_images_in(dir: &Path, images: &[(Type, Vec<u8>)]) -> Result<Vec<(Type, Vec<u8>)>, Error> {
+    let mut images = images.to_vec();
+    let entries = fs::read_dir(dir)
+        .map_err(|e| Error::Internal(format!("failed to read {}: {e}", dir.display())))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| Error::Internal(format!("failed to read {}: {e}", dir.display())))?
+            .path();
+        let Some(Ok(image_type)) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::parse::<Type>)
+        else {
+            continue;
+        };
+        let data = read(&path)?;
+        images.retain(|(t, _)| *t != image_type);
+        images.push((image_type, data));
+    }
+    Ok(images)
+}
+
+fn read(path: impl AsRef<Path>) -> Result<Vec<u8>, Error> {
+    let path = path.as_ref();
+    fs::read(path).map_err(|e| Error::Internal(format!("failed to read {}: {e}", path.display())))
+}
+
+/// The current [`Material`], replaced as a whole on reload so requests in
+/// flight keep the snapshot they started with.
+pub struct Assets {
+    current: RwLock<Arc<Material>>,
+}
+
+impl Assets {
+    pub fn new(material: Material) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(material)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Material> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Loads the configuration and every file it references again. On
+    /// failure the previous material stays in use. SSO, policy and database
+    /// settings are not reloaded; changing them requires a restart.
+    pub fn reload(&self) -> Result<(), Error> {
+        let config = Config::load().map_err(|e| Error::Internal(e.to_string()))?;
+        let material = Material::load(config)?;
+        log_public_key(&material.qr.key_ring);
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(material);
+        info!("Reloaded signing keys, certificates and pass images");
+        Ok(())
+    }
+
+    /// Reloads on `SIGHUP`, e.g. after a certificate renewal.
+    #[cfg(unix)]
+    pub fn spawn_reload_on_sighup(assets: Arc<Self>) -> std::io::Result<()> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                if let Err(e) = assets.reload() {
+                    error!("Reload on SIGHUP failed, keeping previous material: {e}");
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/reload",
+    params(
+        ("Authorization" = String, Header, description = "Bearer token of an admin")
+    ),
+    responses(
+        (status = 204, description = "Signing keys, certificates and pass images reloaded"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks the admin group", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Reload failed; the previous material stays in use", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn reload(req: HttpRequest, assets: web::Data<Assets>) -> Result<HttpResponse, Error> {
+    let admin = require_admin(&req).await?;
+    assets.reload()?;
+    info!("Reload triggered by {}", admin.preferred_username);
+    Ok(HttpResponse::NoContent().finish())
+}
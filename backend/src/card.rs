@@ -0,0 +1,248 @@
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str};
+use qrcode::{Color, EcLevel, QrCode};
+
+/// ISO/IEC 7810 ID-1 card size in points.
+const WIDTH: f32 = 85.6 / 25.4 * 72.0;
+const HEIGHT: f32 = 53.98 / 25.4 * 72.0;
+/// Distance of the content from the card edge, also the QR code's quiet zone.
+const MARGIN: f32 = 9.0;
+const LOGO_HEIGHT: f32 = 20.0;
+/// Side of the QR code, leaving room for the logo row above it.
+const QR_SIZE: f32 = HEIGHT - 2.0 * MARGIN - LOGO_HEIGHT - 10.0;
+/// Width of the text column left of the QR code.
+const TEXT_WIDTH: f32 = WIDTH - 3.0 * MARGIN - QR_SIZE;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+const LOGO: Name = Name(b"Im1");
+
+/// The logo, decoded and compressed for embedding into cards.
+pub struct CardLogo {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+    alpha: Option<Vec<u8>>,
+}
+
+impl CardLogo {
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let invalid = |e: png::DecodingError| format!("invalid card logo: {e}");
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(invalid)?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).map_err(invalid)?;
+        pixels.truncate(info.buffer_size());
+
+        let (rgb, alpha): (Vec<u8>, Option<Vec<u8>>) = match info.color_type {
+            png::ColorType::Rgb => (pixels, None),
+            png::ColorType::Rgba => (
+                pixels
+                    .chunks(4)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                    .collect(),
+                Some(pixels.chunks(4).map(|pixel| pixel[3]).collect()),
+            ),
+            png::ColorType::Grayscale => (pixels.iter().flat_map(|&g| [g, g, g]).collect(), None),
+            png::ColorType::GrayscaleAlpha => (
+                pixels.chunks(2).flat_map(|pixel| [pixel[0]; 3]).collect(),
+                Some(pixels.chunks(2).map(|pixel| pixel[1]).collect()),
+            ),
+            png::ColorType::Indexed => return Err("invalid card logo: unexpanded palette".into()),
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            rgb: compress_to_vec_zlib(&rgb, 6),
+            alpha: alpha.map(|alpha| compress_to_vec_zlib(&alpha, 6)),
+        })
+    }
+}
+
+/// What is printed on a card, already in the holder's language.
+pub struct CardContent {
+    pub title: String,
+    pub name: String,
+    pub subtitle: Option<String>,
+    /// Label and value pairs printed below the name.
+    pub fields: Vec<(String, String)>,
+    /// The signed QR code data.
+    pub qr: String,
+}
+
+/// Writes a single-page PDF the size of an ID-1 card with the logo, the
+/// texts of `card` and its QR code drawn as vector modules.
+pub fn write_card(card: &CardContent, logo: &CardLogo) -> Result<Vec<u8>, String> {
+    let code = QrCode::with_error_correction_level(&card.qr, EcLevel::M)
+        .map_err(|e| format!("failed to encode QR code: {e}"))?;
+
+    let catalog_id = Ref::new(1);
+    let pages_id = Ref::new(2);
+    let page_id = Ref::new(3);
+    let content_id = Ref::new(4);
+    let regular_id = Ref::new(5);
+    let bold_id = Ref::new(6);
+    let logo_id = Ref::new(7);
+    let mask_id = Ref::new(8);
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(pages_id);
+    pdf.pages(pages_id).kids([page_id]).count(1);
+    let mut page = pdf.page(page_id);
+    page.parent(pages_id)
+        .media_box(Rect::new(0.0, 0.0, WIDTH, HEIGHT))
+        .contents(content_id);
+    let mut resources = page.resources();
+    resources
+        .fonts()
+        .pair(REGULAR, regular_id)
+        .pair(BOLD, bold_id);
+    resources.x_objects().pair(LOGO, logo_id);
+    resources.finish();
+    page.finish();
+
+    for (id, font) in [(regular_id, "Helvetica"), (bold_id, "Helvetica-Bold")] {
+        pdf.type1_font(id)
+            .base_font(Name(font.as_bytes()))
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    let mut image = pdf.image_xobject(logo_id, &logo.rgb);
+    image.filter(Filter::FlateDecode);
+    image.width(logo.width as i32);
+    image.height(logo.height as i32);
+    image.color_space().device_rgb();
+    image.bits_per_component(8);
+    if logo.alpha.is_some() {
+        image.s_mask(mask_id);
+    }
+    image.finish();
+    if let Some(alpha) = &logo.alpha {
+        let mut mask = pdf.image_xobject(mask_id, alpha);
+        mask.filter(Filter::FlateDecode);
+        mask.width(logo.width as i32);
+        mask.height(logo.height as i32);
+        mask.color_space().device_gray();
+        mask.bits_per_component(8);
+        mask.finish();
+    }
+
+    let mut content = Content::new();
+    // Cutting guide along the card edge.
+    content
+        .set_stroke_gray(0.75)
+        .set_line_width(0.5)
+        .rect(0.25, 0.25, WIDTH - 0.5, HEIGHT - 0.5)
+        .stroke();
+
+    let logo_width = LOGO_HEIGHT * logo.width as f32 / logo.height as f32;
+    let logo_y = HEIGHT - MARGIN - LOGO_HEIGHT;
+    content
+        .save_state()
+        .transform([logo_width, 0.0, 0.0, LOGO_HEIGHT, MARGIN, logo_y])
+        .x_object(LOGO)
+        .restore_state();
+    let title_x = MARGIN + logo_width + 6.0;
+    text(
+        &mut content,
+        BOLD,
+        10.0,
+        title_x,
+        logo_y + 6.5,
+        WIDTH - MARGIN - title_x,
+        &card.title,
+    );
+
+    let mut y = logo_y - 18.0;
+    text(&mut content, BOLD, 12.0, MARGIN, y, TEXT_WIDTH, &card.name);
+    if let Some(subtitle) = &card.subtitle {
+        y -= 11.0;
+        text(&mut content, REGULAR, 8.0, MARGIN, y, TEXT_WIDTH, subtitle);
+    }
+    y -= 7.0;
+    for (label, value) in &card.fields {
+        if y - 16.0 < MARGIN {
+            break;
+        }
+        content.set_fill_gray(0.45);
+        text(
+            &mut content,
+            REGULAR,
+            5.5,
+            MARGIN,
+            y - 8.0,
+            TEXT_WIDTH,
+            label,
+        );
+        content.set_fill_gray(0.0);
+        text(
+            &mut content,
+            REGULAR,
+            8.0,
+            MARGIN,
+            y - 16.0,
+            TEXT_WIDTH,
+            value,
+        );
+        y -= 18.0;
+    }
+
+    let module = QR_SIZE / code.width() as f32;
+    let qr_x = WIDTH - MARGIN - QR_SIZE;
+    content.set_fill_gray(0.0);
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == Color::Dark {
+            let (column, row) = (i % code.width(), i / code.width());
+            content.rect(
+                qr_x + column as f32 * module,
+                MARGIN + QR_SIZE - (row + 1) as f32 * module,
+                module,
+                module,
+            );
+        }
+    }
+    content.fill_nonzero();
+
+    pdf.stream(content_id, &content.finish());
+    Ok(pdf.finish())
+}
+
+/// Shows `value` at `(x, y)`, shrinking it to fit `width` down to two thirds
+/// of `size` and cutting it off beyond that.
+fn text(content: &mut Content, font: Name, size: f32, x: f32, y: f32, width: f32, value: &str) {
+    // Helvetica averages about half an em per character.
+    let em = if font == BOLD { 0.58 } else { 0.53 };
+    let mut encoded = win_ansi(value);
+    let fitted = (width / (encoded.len() as f32 * em)).clamp(size * 2.0 / 3.0, size);
+    let max_chars = (width / (fitted * em)) as usize;
+    if encoded.len() > max_chars {
+        encoded.truncate(max_chars.saturating_sub(1));
+        encoded.push(0x85); // ellipsis
+    }
+    content
+        .begin_text()
+        .set_font(font, fitted)
+        .next_line(x, y)
+        .show(Str(&encoded))
+        .end_text();
+}
+
+/// Encodes `text` for the standard fonts, replacing what they cannot show.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            '€' => 0x80,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            _ => b'?',
+        })
+        .collect()
+}
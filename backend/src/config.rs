@@ -0,0 +1,344 @@
+use member_id_verify::Format;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{env, fs};
+
+use crate::keys::KeyRing;
+use crate::semester::Calendar;
+use crate::template::PassTemplate;
+
+const DEFAULT_DATABASE_PATH: &str = "member-id.db";
+const DEFAULT_RESOURCES_DIR: &str = "resources";
+const DEFAULT_JWT_LEEWAY: u64 = 60;
+const DEFAULT_JWKS_CACHE_TTL: u64 = 300;
+const DEFAULT_JWKS_MIN_REFETCH_INTERVAL: u64 = 30;
+const DEFAULT_JWKS_MAX_STALE: u64 = 60 * 60 * 24;
+const DEFAULT_APNS_URL: &str = "https://api.push.apple.com";
+const DEFAULT_GOOGLE_WALLET_API_URL: &str = "https://walletobjects.googleapis.com/walletobjects/v1";
+const DEFAULT_GOOGLE_OAUTH_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const DEFAULT_GOOGLE_WALLET_LOGO_URL: &str = "https://id.neuland-ingolstadt.de/gpass-logo.png";
+
+/// Settings read once at startup and shared through `web::Data`.
+pub struct Config {
+    pub sso: SsoConfig,
+    pub admin_group: Option<String>,
+    pub policy_path: Option<String>,
+    pub database_path: String,
+    /// Absolute path of the directory holding the pass images.
+    pub resources_dir: PathBuf,
+    pub qr: QrConfig,
+    pub calendar: Calendar,
+    pub template: PassTemplate,
+    /// `None` if no `PKPASS_*` setting is present.
+    pub apple: Option<AppleConfig>,
+    /// `None` if no `GOOGLE_*` setting is present.
+    pub google: Option<GoogleConfig>,
+}
+
+#[derive(Clone)]
+pub struct SsoConfig {
+    pub issuer_url: Option<String>,
+    pub jwks_url: Option<String>,
+    pub expected_audience: String,
+    pub leeway: u64,
+    pub jwks_cache_ttl: u64,
+    pub jwks_min_refetch_interval: u64,
+    pub jwks_max_stale: u64,
+}
+
+pub struct QrConfig {
+    pub key_ring: KeyRing,
+    pub format: Format,
+    pub issuer: Option<String>,
+    /// Allowlist for the role claim; empty keeps every filtered group.
+    pub roles: Vec<String>,
+}
+
+pub struct AppleConfig {
+    pub organization_name: String,
+    pub pass_type_identifier: String,
+    pub team_identifier: String,
+    pub sign_cert_path: String,
+    pub sign_key_path: String,
+    pub beacon_proximity_uuid: String,
+    /// Base URL of the PassKit web service (`/passkit` on this server);
+    /// passes without it never update.
+    pub web_service_url: Option<String>,
+    /// APNs endpoint for pass update notifications (`APNS_URL`).
+    pub apns_url: String,
+}
+
+pub struct GoogleConfig {
+    pub issuer_id: String,
+    pub class_id: String,
+    pub service_account_email: String,
+    pub service_account_key_path: String,
+    pub logo_url: String,
+    /// Base URL of the Google Wallet REST API (`GOOGLE_WALLET_API_URL`).
+    pub api_url: String,
+    /// OAuth token endpoint for the service account (`GOOGLE_OAUTH_TOKEN_URL`).
+    pub oauth_token_url: String,
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads every setting from the environment, falling back to the TOML
+    /// file at `CONFIG_PATH` where keys are the lowercase variable names
+    /// (e.g. `qr_private_key_hex`). Environment variables take precedence.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut source = Source::new();
+
+        let sso = SsoConfig {
+            issuer_url: source.optional("OIDC_ISSUER_URL"),
+            jwks_url: source.optional("JWKS_URL"),
+            expected_audience: source.required("EXPECTED_AUDIENCE"),
+            leeway: source.parse("JWT_LEEWAY", DEFAULT_JWT_LEEWAY),
+            jwks_cache_ttl: source.parse("JWKS_CACHE_TTL", DEFAULT_JWKS_CACHE_TTL),
+            jwks_min_refetch_interval: source.parse(
+                "JWKS_MIN_REFETCH_INTERVAL",
+                DEFAULT_JWKS_MIN_REFETCH_INTERVAL,
+            ),
+            jwks_max_stale: source.parse("JWKS_MAX_STALE", DEFAULT_JWKS_MAX_STALE),
+        };
+        if sso.issuer_url.is_none() && sso.jwks_url.is_none() {
+            source.problem("either OIDC_ISSUER_URL or JWKS_URL must be set");
+        }
+
+        let key_ring = source.required("QR_PRIVATE_KEY_HEX");
+        let key_ring = if key_ring.is_empty() {
+            None
+        } else {
+            KeyRing::load(
+                &key_ring,
+                source.optional("QR_KEY_ID").as_deref(),
+                &source.optional("QR_PREVIOUS_KEYS").unwrap_or_default(),
+            )
+            .map_err(|e| source.problem(e))
+            .ok()
+        };
+        let qr = key_ring.map(|key_ring| QrConfig {
+            key_ring,
+            format: source.parse("QR_FORMAT", Format::default()),
+            issuer: source.optional("QR_ISSUER"),
+            roles: source
+                .optional("QR_ROLES")
+                .unwrap_or_default()
+                .split(',')
+                .map(|r| r.trim().to_string())
+                .filter(|r| !r.is_empty())
+                .collect(),
+        });
+
+        let calendar = Calendar::load(
+            source.optional("SEMESTER_CALENDAR_PATH").as_deref(),
+            source.parse("SEMESTER_GRACE_DAYS", 0),
+            source.parse("SEMESTER_RENEWAL_WEEKS", 0),
+        )
+        .map_err(|e| source.problem(e))
+        .ok();
+
+        let template = PassTemplate::load(source.optional("PASS_TEMPLATE_PATH").as_deref())
+            .map_err(|e| source.problem(e))
+            .ok();
+
+        let apple = source.any_set(&["PKPASS_"]).then(|| AppleConfig {
+            organization_name: source.required("PKPASS_ORGANIZATION_NAME"),
+            pass_type_identifier: source.required("PKPASS_PASS_TYPE_IDENTIFIER"),
+            team_identifier: source.required("PKPASS_TEAM_IDENTIFIER"),
+            sign_cert_path: source.file("PKPASS_SIGN_CERT_PATH"),
+            sign_key_path: source.file("PKPASS_SIGN_KEY_PATH"),
+            beacon_proximity_uuid: source.required("PKPASS_BEACON_PROXIMITY_UUID"),
+            web_service_url: source.optional("PKPASS_WEB_SERVICE_URL"),
+            apns_url: source
+                .optional("APNS_URL")
+                .unwrap_or_else(|| DEFAULT_APNS_URL.into()),
+        });
+
+        let google = source
+            .any_set_except(
+                &["GOOGLE_WALLET_", "GOOGLE_SERVICE_ACCOUNT_"],
+                &["GOOGLE_WALLET_LOGO_URL", "GOOGLE_WALLET_API_URL"],
+            )
+            .then(|| GoogleConfig {
+                issuer_id: source.required("GOOGLE_WALLET_ISSUER_ID"),
+                class_id: source.required("GOOGLE_WALLET_CLASS_ID"),
+                service_account_email: source.required("GOOGLE_SERVICE_ACCOUNT_EMAIL"),
+                service_account_key_path: source.file("GOOGLE_SERVICE_ACCOUNT_KEY_PATH"),
+                logo_url: source
+                    .optional("GOOGLE_WALLET_LOGO_URL")
+                    .unwrap_or_else(|| DEFAULT_GOOGLE_WALLET_LOGO_URL.into()),
+                api_url: source
+                    .optional("GOOGLE_WALLET_API_URL")
+                    .unwrap_or_else(|| DEFAULT_GOOGLE_WALLET_API_URL.into()),
+                oauth_token_url: source
+                    .optional("GOOGLE_OAUTH_TOKEN_URL")
+                    .unwrap_or_else(|| DEFAULT_GOOGLE_OAUTH_TOKEN_URL.into()),
+            });
+
+        let policy_path = source.optional("POLICY_PATH");
+        if let Some(path) = &policy_path
+            && !Path::new(path).is_file()
+        {
+            source.problem(format!("POLICY_PATH: {path} does not exist"));
+        }
+        let admin_group = source.optional("ADMIN_GROUP");
+        let database_path = source
+            .optional("DATABASE_PATH")
+            .unwrap_or_else(|| DEFAULT_DATABASE_PATH.into());
+        let resources_dir = source
+            .optional("RESOURCES_DIR")
+            .unwrap_or_else(|| DEFAULT_RESOURCES_DIR.into());
+        let resources_dir = std::path::absolute(&resources_dir).unwrap_or_else(|e| {
+            source.problem(format!("RESOURCES_DIR: {resources_dir}: {e}"));
+            PathBuf::new()
+        });
+        if apple.is_some() && !resources_dir.is_dir() {
+            source.problem(format!(
+                "RESOURCES_DIR: {} is not a directory",
+                resources_dir.display()
+            ));
+        }
+
+        match (qr, calendar, template) {
+            (Some(qr), Some(calendar), Some(template)) if source.problems.is_empty() => {
+                Ok(Config {
+                    sso,
+                    admin_group,
+                    policy_path,
+                    database_path,
+                    resources_dir,
+                    qr,
+                    calendar,
+                    template,
+                    apple,
+                    google,
+                })
+            }
+            _ => Err(ConfigError(source.problems)),
+        }
+    }
+}
+
+/// Looks settings up in the environment and the optional TOML file while
+/// collecting problems instead of stopping at the first one.
+struct Source {
+    file: toml::Table,
+    problems: Vec<String>,
+}
+
+impl Source {
+    fn new() -> Self {
+        let mut source = Source {
+            file: toml::Table::new(),
+            problems: Vec::new(),
+        };
+        if let Ok(path) = env::var("CONFIG_PATH")
+            && !path.is_empty()
+        {
+            match fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| content.parse::<toml::Table>().map_err(|e| e.to_string()))
+            {
+                Ok(file) => source.file = file,
+                Err(e) => source.problem(format!("CONFIG_PATH: cannot load {path}: {e}")),
+            }
+        }
+        source
+    }
+
+    fn problem(&mut self, problem: impl Display) {
+        self.problems.push(problem.to_string());
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        if let Ok(value) = env::var(name)
+            && !value.is_empty()
+        {
+            return Some(value);
+        }
+        let value = match self.file.get(&name.to_lowercase())? {
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    toml::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            other => other.to_string(),
+        };
+        Some(value).filter(|v| !v.is_empty())
+    }
+
+    fn optional(&self, name: &str) -> Option<String> {
+        self.get(name)
+    }
+
+    fn required(&mut self, name: &str) -> String {
+        self.get(name).unwrap_or_else(|| {
+            self.problem(format!("{name} is not set"));
+            String::new()
+        })
+    }
+
+    /// A required path that must point to a readable file.
+    fn file(&mut self, name: &str) -> String {
+        let path = self.required(name);
+        if !path.is_empty() && !Path::new(&path).is_file() {
+            self.problem(format!("{name}: {path} does not exist"));
+        }
+        path
+    }
+
+    fn parse<T>(&mut self, name: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.get(name) {
+            Some(value) => value.parse().unwrap_or_else(|e| {
+                self.problem(format!("{name}: invalid value '{value}': {e}"));
+                default
+            }),
+            None => default,
+        }
+    }
+
+    /// Whether any setting starting with one of `prefixes` is present, which
+    /// enables the corresponding optional feature.
+    fn any_set(&self, prefixes: &[&str]) -> bool {
+        self.any_set_except(prefixes, &[])
+    }
+
+    /// Like [`Source::any_set`], ignoring the settings in `defaulted` that
+    /// have a default and therefore don't opt into the feature.
+    fn any_set_except(&self, prefixes: &[&str], defaulted: &[&str]) -> bool {
+        let counts = |name: &str| !defaulted.iter().any(|d| d.eq_ignore_ascii_case(name));
+        prefixes.iter().any(|prefix| {
+            let file_prefix = prefix.to_lowercase();
+            env::vars()
+                .any(|(name, value)| name.starts_with(prefix) && !value.is_empty() && counts(&name))
+                || self
+                    .file
+                    .keys()
+                    .any(|key| key.starts_with(&file_prefix) && counts(key))
+        })
+    }
+}
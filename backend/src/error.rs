@@ -0,0 +1,110 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::error;
+use serde::Serialize;
+use std::fmt;
+
+use crate::policy::Denial;
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Failure of a request, rendered as an RFC 7807 problem document.
+#[derive(Debug)]
+pub enum Error {
+    /// Missing, malformed or unverifiable bearer token.
+    Unauthorized(String),
+    /// Valid token, but the authorization policy denies the request.
+    Forbidden(String),
+    /// The addressed revocation or issuance does not exist, or the requested
+    /// wallet is not configured.
+    NotFound(String),
+    /// The token verified but lacks data needed to issue a credential.
+    Unprocessable(String),
+    /// An upstream service (SSO, Google Wallet) is unreachable, or the
+    /// semester calendar has run out.
+    Unavailable(String),
+    /// Misconfiguration or a local failure; only logged, never sent.
+    Internal(String),
+}
+
+/// Problem details (RFC 7807) returned for every error response.
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    pub problem_type: String,
+    #[schema(example = "Forbidden")]
+    pub title: String,
+    #[schema(example = 403)]
+    pub status: u16,
+    #[schema(example = "pkpass requires group 'mitglieder'")]
+    pub detail: Option<String>,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unauthorized(msg) => write!(f, "unauthorized: {msg}"),
+            Error::Forbidden(msg) => write!(f, "forbidden: {msg}"),
+            Error::NotFound(msg) => write!(f, "not found: {msg}"),
+            Error::Unprocessable(msg) => write!(f, "unprocessable: {msg}"),
+            Error::Unavailable(msg) => write!(f, "service unavailable: {msg}"),
+            Error::Internal(msg) => write!(f, "internal error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let detail = match self {
+            Error::Unauthorized(msg)
+            | Error::Forbidden(msg)
+            | Error::NotFound(msg)
+            | Error::Unprocessable(msg) => Some(msg.clone()),
+            Error::Unavailable(_) | Error::Internal(_) => {
+                error!("{self}");
+                None
+            }
+        };
+        HttpResponse::build(status)
+            .content_type(PROBLEM_CONTENT_TYPE)
+            .json(Problem {
+                problem_type: "about:blank".into(),
+                title: status.canonical_reason().unwrap_or_default().into(),
+                status: status.as_u16(),
+                detail,
+            })
+    }
+}
+
+impl From<Denial> for Error {
+    fn from(denial: Denial) -> Self {
+        Error::Forbidden(denial.0)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Internal(format!("database: {e}"))
+    }
+}
+
+impl From<member_id_verify::Error> for Error {
+    fn from(e: member_id_verify::Error) -> Self {
+        Error::Internal(format!("QR encoding: {e}"))
+    }
+}
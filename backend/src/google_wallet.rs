@@ -0,0 +1,555 @@
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use log::{debug, info, warn};
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::assets::{Assets, Material};
+use crate::config::GoogleConfig;
+use crate::error::Error;
+use crate::passes::{IssuedObject, generic_object};
+use crate::policy::Policy;
+use crate::revocation::now;
+use crate::semester::Calendar;
+use crate::store::{Store, WalletObject};
+use crate::utils::Claims;
+
+const SCOPE: &str = "https://www.googleapis.com/auth/wallet_object.issuer";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SEMESTER_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Object states used by the Google Wallet API.
+pub const ACTIVE: &str = "ACTIVE";
+pub const EXPIRED: &str = "EXPIRED";
+pub const INACTIVE: &str = "INACTIVE";
+
+/// Errors of Wallet API calls; `Send` so they can run in spawned tasks.
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Client for the Google Wallet REST API, authenticated as the service
+/// account.
+pub struct WalletClient {
+    client: reqwest::Client,
+    api_url: String,
+    token_url: String,
+    service_account_email: String,
+    encoding_key: EncodingKey,
+    /// Class definitions by full class ID.
+    classes: HashMap<String, Value>,
+    /// Classes created or updated by this client.
+    ready_classes: tokio::sync::Mutex<HashSet<String>>,
+    token: tokio::sync::Mutex<Option<(String, Instant)>>,
+}
+
+impl WalletClient {
+    pub fn new(
+        config: &GoogleConfig,
+        encoding_key: EncodingKey,
+        classes: HashMap<String, Value>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            api_url: config.api_url.trim_end_matches('/').to_string(),
+            token_url: config.oauth_token_url.clone(),
+            service_account_email: config.service_account_email.clone(),
+            encoding_key,
+            classes,
+            ready_classes: tokio::sync::Mutex::new(HashSet::new()),
+            token: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    /// Exchanges a signed assertion for an access token, reused until shortly
+    /// before it expires.
+    async fn access_token(&self) -> Result<String, BoxError> {
+        let mut token = self.token.lock().await;
+        if let Some((access_token, expires_at)) = token.as_ref()
+            && Instant::now() < *expires_at
+        {
+            return Ok(access_token.clone());
+        }
+
+        let iat = now();
+        let assertion = jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &json!({
+                "iss": self.service_account_email,
+                "scope": SCOPE,
+                "aud": self.token_url,
+                "iat": iat,
+                "exp": iat + 3600,
+            }),
+            &self.encoding_key,
+        )?;
+        let response: AccessToken = self
+            .client
+            .post(&self.token_url)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &assertion),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let expires_at =
+            Instant::now() + Duration::from_secs(response.expires_in.saturating_sub(60));
+        *token = Some((response.access_token.clone(), expires_at));
+        Ok(response.access_token)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<reqwest::Response, BoxError> {
+        let mut request = self
+            .client
+            .request(method, format!("{}/{path}", self.api_url))
+            .bearer_auth(self.access_token().await?);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        Ok(request.send().await?)
+    }
+
+    /// Creates the class, or updates it to the current definition, once per
+    /// client.
+    async fn ensure_class(&self, class_id: &str) -> Result<(), BoxError> {
+        let mut ready_classes = self.ready_classes.lock().await;
+        if ready_classes.contains(class_id) {
+            return Ok(());
+        }
+        let class = self
+            .classes
+            .get(class_id)
+            .ok_or_else(|| format!("unknown Google Wallet class {class_id}"))?;
+        let path = format!("genericClass/{class_id}");
+        let response = match self.send(Method::GET, &path, None).await?.status() {
+            StatusCode::NOT_FOUND => self.send(Method::POST, "genericClass", Some(class)).await?,
+            _ => self.send(Method::PUT, &path, Some(class)).await?,
+        };
+        response.error_for_status()?;
+        info!("Google Wallet class {class_id} is up to date");
+        ready_classes.insert(class_id.to_string());
+        Ok(())
+    }
+
+    /// Inserts the object, or patches it if it already exists.
+    pub async fn upsert_object(&self, object: &IssuedObject) -> Result<(), BoxError> {
+        self.ensure_class(&object.class_id).await?;
+        let response = self
+            .send(Method::POST, "genericObject", Some(&object.value))
+            .await?;
+        if response.status() == StatusCode::CONFLICT {
+            self.send(
+                Method::PATCH,
+                &format!("genericObject/{}", object.id),
+                Some(&object.value),
+            )
+            .await?
+            .error_for_status()?;
+        } else {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_state(&self, object_id: &str, state: &str) -> Result<(), BoxError> {
+        self.send(
+            Method::PATCH,
+            &format!("genericObject/{object_id}"),
+            Some(&json!({ "state": state })),
+        )
+        .await?
+        .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Records the object of a downloaded pass and stores it server-side, so it
+/// can be patched later.
+pub fn register_object(
+    store: &web::Data<Store>,
+    material: &Arc<Material>,
+    claims: &Claims,
+    object: IssuedObject,
+) -> Result<(), Error> {
+    store.save_wallet_object(&WalletObject {
+        sub: claims.sub.clone(),
+        object_id: object.id.clone(),
+        given_name: claims.given_name.clone(),
+        preferred_username: claims.preferred_username.clone(),
+        groups: claims.groups.clone(),
+        client: claims.azp.clone(),
+        semester: material.calendar.pass_semester(Utc::now())?.name,
+        state: ACTIVE.into(),
+        jti: Some(object.jti.clone()),
+        refreshed_at: now(),
+        updated_at: now(),
+        language: claims.language,
+    })?;
+    let material = material.clone();
+    tokio::spawn(async move {
+        let Some(google) = &material.google else {
+            return;
+        };
+        match google.api.upsert_object(&object).await {
+            Ok(()) => debug!("Stored Google Wallet object {}", object.id),
+            Err(e) => warn!("Failed to store Google Wallet object {}: {e}", object.id),
+        }
+    });
+    Ok(())
+}
+
+/// Updates the holder data of an existing object from a fresh SSO token.
+/// Changed names or groups, and any fetch after a member revocation, rebuild
+/// the object with a new code, as far as [`allowed_state`] permits.
+pub fn refresh_holder(
+    store: &web::Data<Store>,
+    policy: &Policy,
+    assets: &Assets,
+    claims: &Claims,
+) -> Result<(), Error> {
+    let Some(mut object) = store.wallet_object(&claims.sub)? else {
+        return Ok(());
+    };
+    let changed = object.given_name != claims.given_name
+        || object.preferred_username != claims.preferred_username
+        || object.groups != claims.groups;
+    object.given_name = claims.given_name.clone();
+    object.preferred_username = claims.preferred_username.clone();
+    object.groups = claims.groups.clone();
+    object.refreshed_at = now();
+    let material = assets.current();
+    let state = allowed_state(store, &material.calendar, policy, &object)?;
+    if !changed && object.state == state {
+        store.save_wallet_object(&object)?;
+        return Ok(());
+    }
+    object.state = state.into();
+    object.updated_at = now();
+    store.save_wallet_object(&object)?;
+    sync(store, &material, object);
+    Ok(())
+}
+
+/// Sets the state of the member's object, e.g. `INACTIVE` on revocation.
+/// Reactivating rebuilds the object with a new code.
+pub fn set_member_state(
+    store: &web::Data<Store>,
+    policy: &Policy,
+    assets: &Assets,
+    sub: &str,
+    state: &str,
+) -> Result<(), Error> {
+    if let Some(object) = store.wallet_object(sub)? {
+        set_state(store, policy, &assets.current(), object, state)?;
+    }
+    Ok(())
+}
+
+/// Sets the state of the object carrying the code `jti`, e.g. `EXPIRED`
+/// when that code is revoked.
+pub fn set_code_state(
+    store: &web::Data<Store>,
+    policy: &Policy,
+    assets: &Assets,
+    jti: &str,
+    state: &str,
+) -> Result<(), Error> {
+    if let Some(object) = store.wallet_object_by_jti(jti)? {
+        set_state(store, policy, &assets.current(), object, state)?;
+    }
+    Ok(())
+}
+
+/// Sets the state of `object`; `ACTIVE` only takes effect as far as
+/// [`allowed_state`] permits.
+fn set_state(
+    store: &web::Data<Store>,
+    policy: &Policy,
+    material: &Arc<Material>,
+    mut object: WalletObject,
+    state: &str,
+) -> Result<(), Error> {
+    let state = match state {
+        ACTIVE => allowed_state(store, &material.calendar, policy, &object)?,
+        state => state,
+    };
+    object.state = state.into();
+    object.updated_at = now();
+    store.save_wallet_object(&object)?;
+    sync(store, material, object);
+    Ok(())
+}
+
+/// The best state an object may have given its stored holder data:
+/// `EXPIRED` while the code on it is revoked or the holder has not signed in
+/// since the previous semester, `INACTIVE` while the member is revoked or
+/// the policy no longer grants their groups a Google Wallet pass, `ACTIVE`
+/// otherwise.
+fn allowed_state(
+    store: &Store,
+    calendar: &Calendar,
+    policy: &Policy,
+    object: &WalletObject,
+) -> Result<&'static str, Error> {
+    let code_revoked = match &object.jti {
+        Some(jti) => store
+            .issuance(jti)?
+            .is_some_and(|issuance| issuance.revoked_at.is_some()),
+        None => false,
+    };
+    let refreshed_at = DateTime::from_timestamp(object.refreshed_at as i64, 0).unwrap_or_default();
+    if code_revoked || !calendar.is_fresh(refreshed_at, Utc::now())? {
+        return Ok(EXPIRED);
+    }
+    let member_revoked = store
+        .revocation(&object.sub)?
+        .is_some_and(|revocation| revocation.revoked_at >= object.refreshed_at);
+    if member_revoked
+        || policy
+            .authorize("gpass", Some("wa"), &holder_claims(object))
+            .is_err()
+    {
+        return Ok(INACTIVE);
+    }
+    Ok(ACTIVE)
+}
+
+/// The holder data an object was last built from.
+fn holder_claims(object: &WalletObject) -> Claims {
+    Claims {
+        sub: object.sub.clone(),
+        given_name: object.given_name.clone(),
+        preferred_username: object.preferred_username.clone(),
+        groups: object.groups.clone(),
+        azp: object.client.clone(),
+        language: object.language,
+    }
+}
+
+/// Brings the server-side object in line with the stored record in the
+/// background: active objects are rebuilt with a fresh code, others only
+/// get their state.
+fn sync(store: &web::Data<Store>, material: &Arc<Material>, object: WalletObject) {
+    let store = store.clone();
+    let material = material.clone();
+    tokio::spawn(async move {
+        let Some(google) = &material.google else {
+            return;
+        };
+        if object.state != ACTIVE {
+            if let Err(e) = google.api.set_state(&object.object_id, &object.state).await {
+                warn!(
+                    "Failed to set Google Wallet object {} to {}: {e}",
+                    object.object_id, object.state
+                );
+            }
+            return;
+        }
+
+        let issued = match generic_object(&holder_claims(&object), &store, &material) {
+            Ok(issued) => issued,
+            Err(e) => {
+                warn!(
+                    "Failed to build Google Wallet object {}: {e}",
+                    object.object_id
+                );
+                return;
+            }
+        };
+        if let Err(e) = store.set_wallet_object_jti(&object.sub, &issued.jti) {
+            warn!(
+                "Failed to record code of Google Wallet object {}: {e}",
+                object.object_id
+            );
+        }
+        match google.api.upsert_object(&issued).await {
+            Ok(()) => debug!("Updated Google Wallet object {}", issued.id),
+            Err(e) => warn!("Failed to update Google Wallet object {}: {e}", issued.id),
+        }
+    });
+}
+
+/// Rebuilds every active object issued for another semester than the one
+/// passes are issued for now, unless its holder lapsed.
+fn roll_over_semester(
+    store: &web::Data<Store>,
+    policy: &Policy,
+    material: &Arc<Material>,
+) -> Result<(), Error> {
+    let semester = material.calendar.pass_semester(Utc::now())?.name;
+    let objects = store.stale_wallet_objects(&semester)?;
+    if !objects.is_empty() {
+        info!(
+            "Semester changed, updating {} Google Wallet objects",
+            objects.len()
+        );
+    }
+    for mut object in objects {
+        object.semester = semester.clone();
+        object.updated_at = now();
+        if object.state != ACTIVE {
+            store.save_wallet_object(&object)?;
+            continue;
+        }
+        object.state = allowed_state(store, &material.calendar, policy, &object)?.into();
+        if object.state != ACTIVE {
+            debug!("Google Wallet object {} lapsed", object.object_id);
+        }
+        store.save_wallet_object(&object)?;
+        sync(store, material, object);
+    }
+    Ok(())
+}
+
+/// Checks for a semester change every hour.
+pub fn spawn_semester_rollover(
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    assets: web::Data<Assets>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SEMESTER_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = roll_over_semester(&store, &policy, &assets.current()) {
+                warn!("Google Wallet semester roll-over failed: {e}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{Issuance, Revocation};
+
+    const SUB: &str = "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f";
+
+    fn object(groups: &[&str], refreshed_at: u64) -> WalletObject {
+        WalletObject {
+            sub: SUB.into(),
+            object_id: format!("issuer.{SUB}"),
+            given_name: "Max".into(),
+            preferred_username: "max".into(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            client: None,
+            semester: calendar().pass_semester(Utc::now()).unwrap().name,
+            state: ACTIVE.into(),
+            jti: Some("shown".into()),
+            refreshed_at,
+            updated_at: refreshed_at,
+            language: Default::default(),
+        }
+    }
+
+    fn calendar() -> Calendar {
+        Calendar::load(None, 0, 0).unwrap()
+    }
+
+    fn store() -> Store {
+        let store = Store::open(":memory:").unwrap();
+        store
+            .record_issuance(&Issuance {
+                jti: "shown".into(),
+                sub: SUB.into(),
+                t: "wa".into(),
+                iat: now() - 60,
+                exp: now() + 3600,
+                client: None,
+                revoked_at: None,
+                revoked_by: None,
+                revoke_reason: None,
+            })
+            .unwrap();
+        store
+    }
+
+    #[test]
+    fn activates_fresh_authorized_holders() {
+        let state = allowed_state(
+            &store(),
+            &calendar(),
+            &Policy::default(),
+            &object(&["mitglieder"], now()),
+        );
+        assert_eq!(state.unwrap(), ACTIVE);
+    }
+
+    #[test]
+    fn keeps_revoked_codes_expired() {
+        let store = store();
+        assert!(
+            store
+                .revoke_issuance("shown", now(), "admin", None)
+                .unwrap()
+        );
+        let state = allowed_state(
+            &store,
+            &calendar(),
+            &Policy::default(),
+            &object(&["mitglieder"], now()),
+        );
+        assert_eq!(state.unwrap(), EXPIRED);
+    }
+
+    #[test]
+    fn expires_holders_who_did_not_sign_in_recently() {
+        let state = allowed_state(
+            &store(),
+            &calendar(),
+            &Policy::default(),
+            &object(&["mitglieder"], 0),
+        );
+        assert_eq!(state.unwrap(), EXPIRED);
+    }
+
+    #[test]
+    fn deactivates_revoked_members_and_denied_groups() {
+        let store = store();
+        let policy = Policy::default();
+        assert_eq!(
+            allowed_state(&store, &calendar(), &policy, &object(&["gäste"], now())).unwrap(),
+            INACTIVE
+        );
+
+        let refreshed_at = now() - 60;
+        store
+            .revoke(&Revocation {
+                sub: SUB.into(),
+                reason: None,
+                revoked_at: now(),
+                revoked_by: "admin".into(),
+            })
+            .unwrap();
+        let revoked = object(&["mitglieder"], refreshed_at);
+        assert_eq!(
+            allowed_state(&store, &calendar(), &policy, &revoked).unwrap(),
+            INACTIVE
+        );
+        // A fresh SSO token after the revocation reactivates the object.
+        let refreshed = object(&["mitglieder"], now() + 1);
+        assert_eq!(
+            allowed_state(&store, &calendar(), &policy, &refreshed).unwrap(),
+            ACTIVE
+        );
+    }
+}
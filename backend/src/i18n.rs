@@ -0,0 +1,132 @@
+use chrono::{DateTime, Utc};
+
+/// Languages passes are available in; German is the default.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Language {
+    #[default]
+    De,
+    En,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::De, Language::En];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::De => "de",
+            Language::En => "en",
+        }
+    }
+
+    /// Matches the primary subtag of a language tag such as `en-GB`.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim();
+        Self::ALL
+            .into_iter()
+            .find(|language| language.code().eq_ignore_ascii_case(primary))
+    }
+}
+
+/// Back field text explaining that a pass outlives its semester.
+pub fn grace_period(
+    language: Language,
+    days: i64,
+    end: DateTime<Utc>,
+    valid_until: DateTime<Utc>,
+) -> String {
+    let end = end.format("%Y-%m-%d");
+    let valid_until = valid_until.format("%Y-%m-%d");
+    match language {
+        Language::De => {
+            format!("Gilt noch {days} Tage nach Semesterende ({end}) bis {valid_until}")
+        }
+        Language::En => {
+            format!(
+                "Valid for {days} more days after the end of the semester ({end}) until {valid_until}"
+            )
+        }
+    }
+}
+
+/// A text in every supported language.
+#[derive(Clone)]
+pub struct Localized(Vec<(Language, String)>);
+
+impl Localized {
+    pub fn new(text: impl Fn(Language) -> String) -> Self {
+        Self(
+            Language::ALL
+                .into_iter()
+                .map(|language| (language, text(language)))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, language: Language) -> &str {
+        self.0
+            .iter()
+            .find(|(l, _)| *l == language)
+            .map(|(_, text)| text.as_str())
+            .unwrap_or_default()
+    }
+}
+
+/// Collects the texts of an Apple Wallet pass for its `pass.strings` files.
+/// `pass.json` carries the texts in the holder's language, which also serve
+/// as the keys, so Wallet falls back to them for other device languages.
+pub struct PassStrings {
+    language: Language,
+    texts: Vec<Localized>,
+}
+
+impl PassStrings {
+    pub fn new(language: Language) -> Self {
+        Self {
+            language,
+            texts: Vec::new(),
+        }
+    }
+
+    /// Records `text` and returns it in the holder's language.
+    pub fn add(&mut self, text: impl Into<Localized>) -> String {
+        let text = text.into();
+        let key = text.get(self.language).to_string();
+        self.texts.push(text);
+        key
+    }
+
+    /// `<language>.lproj/pass.strings` for every language, UTF-16 encoded.
+    pub fn files(&self) -> Vec<(String, Vec<u8>)> {
+        Language::ALL
+            .into_iter()
+            .map(|language| {
+                let mut strings = String::new();
+                let mut keys = Vec::new();
+                for text in &self.texts {
+                    let key = text.get(self.language);
+                    let untranslated = Language::ALL.into_iter().all(|l| text.get(l) == key);
+                    if untranslated || keys.contains(&key) {
+                        continue;
+                    }
+                    keys.push(key);
+                    strings.push_str(&format!(
+                        "\"{}\" = \"{}\";\n",
+                        escape(key),
+                        escape(text.get(language))
+                    ));
+                }
+                let data = std::iter::once(0xFEFF)
+                    .chain(strings.encode_utf16())
+                    .flat_map(u16::to_le_bytes)
+                    .collect();
+                (format!("{}.lproj/pass.strings", language.code()), data)
+            })
+            .collect()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,146 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use log::info;
+use serde::Deserialize;
+
+use crate::assets::Assets;
+use crate::error::Error;
+use crate::google_wallet::{self, ACTIVE, EXPIRED};
+use crate::passkit::push_updates;
+use crate::policy::Policy;
+use crate::require_admin;
+use crate::revocation::now;
+use crate::store::Store;
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Deserialize, utoipa::IntoParams)]
+pub struct IssuanceQuery {
+    /// Only list issuances of this subject.
+    pub sub: Option<String>,
+    /// Maximum number of entries (default 100, at most 1000).
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct RevokeIssuanceRequest {
+    #[schema(example = "Screenshot shared in group chat")]
+    pub reason: Option<String>,
+}
+
+/// Marks the Apple Wallet pass showing the code `jti` as changed, so devices
+/// fetch it voided or with its code restored. Returns the pass's serial
+/// number if it was touched.
+fn touch_wallet_pass_showing(store: &Store, jti: &str) -> Result<Option<String>, Error> {
+    let Some(issuance) = store.issuance(jti)? else {
+        return Ok(None);
+    };
+    let shown = issuance.t == "wi"
+        && store
+            .latest_issuance(&issuance.sub, "wi")?
+            .is_some_and(|latest| latest.jti == jti);
+    if shown && store.touch_wallet_pass(&issuance.sub, now())? {
+        return Ok(Some(issuance.sub));
+    }
+    Ok(None)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/issuances",
+    params(
+        IssuanceQuery,
+        ("Authorization" = String, Header, description = "Bearer token of an admin")
+    ),
+    responses(
+        (status = 200, description = "Issued QR codes, newest first", body = [Issuance]),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks the admin group", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn list_issuances(
+    req: HttpRequest,
+    query: web::Query<IssuanceQuery>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req).await?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let issuances = store.issuances(query.sub.as_deref(), limit)?;
+    Ok(HttpResponse::Ok().json(issuances))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/issuances/{jti}/revocation",
+    request_body = RevokeIssuanceRequest,
+    params(
+        ("jti" = String, Path, description = "ID of the issued code"),
+        ("Authorization" = String, Header, description = "Bearer token of an admin")
+    ),
+    responses(
+        (status = 204, description = "Code revoked; other codes of the member stay valid"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks the admin group", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown jti", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn revoke_issuance(
+    req: HttpRequest,
+    jti: web::Path<String>,
+    body: web::Json<RevokeIssuanceRequest>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let admin = require_admin(&req).await?;
+    let reason = body.reason.as_deref();
+    if !store.revoke_issuance(&jti, now(), &admin.preferred_username, reason)? {
+        return Err(Error::NotFound(format!("unknown jti {jti}")));
+    }
+    if let Some(serial) = touch_wallet_pass_showing(&store, &jti)? {
+        push_updates(&store, &assets.current(), vec![serial]);
+    }
+    google_wallet::set_code_state(&store, &policy, &assets, &jti, EXPIRED)?;
+    info!(
+        "Revoked issuance {jti} by {}: {}",
+        admin.preferred_username,
+        reason.unwrap_or("-")
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/issuances/{jti}/revocation",
+    params(
+        ("jti" = String, Path, description = "ID of the issued code"),
+        ("Authorization" = String, Header, description = "Bearer token of an admin")
+    ),
+    responses(
+        (status = 204, description = "Revocation removed"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks the admin group", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Code was not revoked", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn unrevoke_issuance(
+    req: HttpRequest,
+    jti: web::Path<String>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let admin = require_admin(&req).await?;
+    if !store.unrevoke_issuance(&jti)? {
+        return Err(Error::NotFound(format!("issuance {jti} is not revoked")));
+    }
+    if let Some(serial) = touch_wallet_pass_showing(&store, &jti)? {
+        push_updates(&store, &assets.current(), vec![serial]);
+    }
+    google_wallet::set_code_state(&store, &policy, &assets, &jti, ACTIVE)?;
+    info!("Unrevoked issuance {jti} by {}", admin.preferred_username);
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,275 @@
+use jsonwebtoken::{Algorithm, DecodingKey};
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::config::SsoConfig;
+use crate::error::Error;
+
+#[derive(Deserialize)]
+struct Jwk {
+    kid: String,
+    kty: String,
+    alg: Option<String>,
+    crv: Option<String>,
+    /// RSA modulus and exponent.
+    n: Option<String>,
+    e: Option<String>,
+    /// EC/OKP public key coordinates.
+    x: Option<String>,
+    y: Option<String>,
+}
+
+impl Jwk {
+    /// Builds the decoding key and picks the algorithm from `kty`/`crv`,
+    /// checked against `alg` if the JWK announces one. Symmetric keys are
+    /// refused: anyone holding them could mint tokens.
+    fn decoding_key(&self) -> Result<(DecodingKey, Algorithm), Box<dyn std::error::Error>> {
+        let (key, algorithms) = match (self.kty.as_str(), self.crv.as_deref()) {
+            ("RSA", _) => (
+                DecodingKey::from_rsa_components(
+                    self.n.as_deref().ok_or("RSA JWK missing n")?,
+                    self.e.as_deref().ok_or("RSA JWK missing e")?,
+                )?,
+                &[
+                    Algorithm::RS256,
+                    Algorithm::RS384,
+                    Algorithm::RS512,
+                    Algorithm::PS256,
+                    Algorithm::PS384,
+                    Algorithm::PS512,
+                ][..],
+            ),
+            ("EC", Some(crv @ ("P-256" | "P-384"))) => (
+                DecodingKey::from_ec_components(
+                    self.x.as_deref().ok_or("EC JWK missing x")?,
+                    self.y.as_deref().ok_or("EC JWK missing y")?,
+                )?,
+                if crv == "P-256" {
+                    &[Algorithm::ES256][..]
+                } else {
+                    &[Algorithm::ES384][..]
+                },
+            ),
+            ("OKP", Some("Ed25519")) => (
+                DecodingKey::from_ed_components(self.x.as_deref().ok_or("OKP JWK missing x")?)?,
+                &[Algorithm::EdDSA][..],
+            ),
+            (kty, crv) => {
+                return Err(format!(
+                    "unsupported JWK key type {kty}{}",
+                    crv.map(|crv| format!(" ({crv})")).unwrap_or_default()
+                )
+                .into());
+            }
+        };
+        let algorithm = match &self.alg {
+            Some(alg) => {
+                let alg = Algorithm::from_str(alg)?;
+                if !algorithms.contains(&alg) {
+                    return Err(
+                        format!("JWK alg {alg:?} does not match key type {}", self.kty).into(),
+                    );
+                }
+                alg
+            }
+            None => algorithms[0],
+        };
+        Ok((key, algorithm))
+    }
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+/// The parts of `/.well-known/openid-configuration` we rely on.
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    jwks_uri: String,
+}
+
+#[derive(Default)]
+struct State {
+    keys: HashMap<String, Jwk>,
+    fetched_at: Option<Instant>,
+    last_attempt: Option<Instant>,
+}
+
+/// In-memory JWKS with a TTL, rate-limited refetching of unknown `kid`s and
+/// stale-if-error fallback when the SSO is unreachable.
+pub struct JwksCache {
+    sso: SsoConfig,
+    state: RwLock<State>,
+    /// Serialises fetches so concurrent misses share a single request.
+    fetch: tokio::sync::Mutex<()>,
+    discovery: tokio::sync::OnceCell<Discovery>,
+    ttl: Duration,
+    min_refetch_interval: Duration,
+    max_stale: Duration,
+}
+
+impl JwksCache {
+    pub fn new(sso: SsoConfig) -> Self {
+        Self {
+            state: RwLock::new(State::default()),
+            fetch: tokio::sync::Mutex::new(()),
+            discovery: tokio::sync::OnceCell::new(),
+            ttl: Duration::from_secs(sso.jwks_cache_ttl),
+            min_refetch_interval: Duration::from_secs(sso.jwks_min_refetch_interval),
+            max_stale: Duration::from_secs(sso.jwks_max_stale),
+            sso,
+        }
+    }
+
+    /// The SSO settings tokens are validated against.
+    pub fn sso(&self) -> &SsoConfig {
+        &self.sso
+    }
+
+    /// Returns the decoding key for `kid` and the algorithm it is used with,
+    /// fetching the JWKS if the cache is expired or does not know the key yet.
+    pub async fn decoding_key(&self, kid: &str) -> Result<(DecodingKey, Algorithm), Error> {
+        let (known, fresh) = {
+            let state = self.read();
+            (
+                state.keys.contains_key(kid),
+                state.fetched_at.is_some_and(|t| t.elapsed() < self.ttl),
+            )
+        };
+
+        if (!fresh || !known)
+            && self.may_refetch()
+            && let Err(e) = self.refresh().await
+        {
+            if !known {
+                return Err(Error::Unavailable(format!("failed to fetch JWKS: {e}")));
+            }
+            warn!("Failed to refresh JWKS, using cached keys: {e}");
+        }
+
+        let state = self.read();
+        if state
+            .fetched_at
+            .is_none_or(|t| t.elapsed() >= self.max_stale)
+        {
+            return Err(Error::Unavailable("JWKS unavailable".into()));
+        }
+        let jwk = state
+            .keys
+            .get(kid)
+            .ok_or_else(|| Error::Unauthorized(format!("unknown signing key {kid}")))?;
+        jwk.decoding_key()
+            .map_err(|e| Error::Unauthorized(format!("unusable signing key {kid}: {e}")))
+    }
+
+    /// Fetches the JWKS and replaces the cached keys. Concurrent callers
+    /// wait for the fetch already in flight instead of issuing their own.
+    pub async fn refresh(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let _guard = self.fetch.lock().await;
+        if self
+            .read()
+            .fetched_at
+            .is_some_and(|fetched_at| fetched_at >= started)
+        {
+            return Ok(());
+        }
+
+        self.write().last_attempt = Some(Instant::now());
+        let jwks_url = self.jwks_url().await?;
+        let jwks: Jwks = reqwest::get(&jwks_url)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut state = self.write();
+        state.keys = jwks.keys.into_iter().map(|k| (k.kid.clone(), k)).collect();
+        state.fetched_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// The issuer tokens must carry: the `issuer` announced by the discovery
+    /// document of `OIDC_ISSUER_URL`, or `None` if only `JWKS_URL` is set.
+    pub async fn issuer(&self) -> Result<Option<String>, Error> {
+        let discovery = self
+            .discovery()
+            .await
+            .map_err(|e| Error::Unavailable(format!("OIDC discovery failed: {e}")))?;
+        Ok(discovery.map(|d| d.issuer.clone()))
+    }
+
+    /// `JWKS_URL` if set, otherwise the `jwks_uri` from OIDC discovery.
+    async fn jwks_url(&self) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(url) = &self.sso.jwks_url {
+            return Ok(url.clone());
+        }
+        match self.discovery().await? {
+            Some(discovery) => Ok(discovery.jwks_uri.clone()),
+            None => Err("neither OIDC_ISSUER_URL nor JWKS_URL is set".into()),
+        }
+    }
+
+    /// Fetches the discovery document once; failures are retried on the next
+    /// call.
+    async fn discovery(&self) -> Result<Option<&Discovery>, Box<dyn std::error::Error>> {
+        let Some(issuer_url) = &self.sso.issuer_url else {
+            return Ok(None);
+        };
+        let discovery = self
+            .discovery
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    issuer_url.trim_end_matches('/')
+                );
+                let discovery: Discovery =
+                    reqwest::get(&url).await?.error_for_status()?.json().await?;
+                if discovery.issuer.trim_end_matches('/') != issuer_url.trim_end_matches('/') {
+                    return Err(format!(
+                        "discovery document announces issuer {}, expected {issuer_url}",
+                        discovery.issuer
+                    )
+                    .into());
+                }
+                Ok::<_, Box<dyn std::error::Error>>(discovery)
+            })
+            .await?;
+        Ok(Some(discovery))
+    }
+
+    /// Keeps the cache warm so requests rarely have to wait for the SSO.
+    pub fn spawn_background_refresh(cache: Arc<Self>) {
+        let period = (cache.ttl / 2).max(Duration::from_secs(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                match cache.refresh().await {
+                    Ok(()) => debug!("Refreshed JWKS ({} keys)", cache.read().keys.len()),
+                    Err(e) => warn!("Background JWKS refresh failed: {e}"),
+                }
+            }
+        });
+    }
+
+    fn may_refetch(&self) -> bool {
+        self.read()
+            .last_attempt
+            .is_none_or(|t| t.elapsed() >= self.min_refetch_interval)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
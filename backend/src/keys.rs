@@ -0,0 +1,226 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, NaiveDate, Utc};
+use member_id_verify::KeyResolver;
+use p256::PublicKey;
+use p256::ecdsa::{SigningKey, VerifyingKey};
+use p256::pkcs8::{EncodePublicKey, LineEnding};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// A QR signing key. Only the active key signs, previous keys are kept so
+/// passes issued before a rotation keep verifying until `not_after`.
+pub struct QrKey {
+    pub kid: String,
+    pub verifying_key: VerifyingKey,
+    pub active: bool,
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+impl QrKey {
+    pub fn is_valid_at(&self, now: DateTime<Utc>) -> bool {
+        self.not_after.is_none_or(|not_after| now <= not_after)
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.verifying_key.to_encoded_point(false).as_bytes())
+    }
+
+    /// SubjectPublicKeyInfo wrapped in a `PUBLIC KEY` PEM block.
+    pub fn public_key_pem(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(PublicKey::from(&self.verifying_key).to_public_key_pem(LineEnding::LF)?)
+    }
+
+    pub fn to_jwk(&self) -> Jwk {
+        let point = self.verifying_key.to_encoded_point(false);
+        let coordinate = |c: Option<&[u8]>| URL_SAFE_NO_PAD.encode(c.unwrap_or_default());
+        Jwk {
+            kty: "EC".into(),
+            crv: "P-256".into(),
+            x: coordinate(point.x().map(|x| x.as_slice())),
+            y: coordinate(point.y().map(|y| y.as_slice())),
+            kid: self.kid.clone(),
+            alg: "ES256".into(),
+            use_: "sig".into(),
+        }
+    }
+}
+
+pub struct KeyRing {
+    signing_key: SigningKey,
+    keys: Vec<QrKey>,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct PublicKeyInfo {
+    #[schema(example = "9c1f0e2a")]
+    pub kid: String,
+    #[schema(example = "04a1b2...")]
+    pub public_key: String,
+    pub active: bool,
+    /// Verification is accepted until this instant; `null` means open-ended.
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct KeySetResponse {
+    pub keys: Vec<PublicKeyInfo>,
+}
+
+/// An EC public key as JSON Web Key (RFC 7517/7518).
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct Jwk {
+    #[schema(example = "EC")]
+    pub kty: String,
+    #[schema(example = "P-256")]
+    pub crv: String,
+    #[schema(example = "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU")]
+    pub x: String,
+    #[schema(example = "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0")]
+    pub y: String,
+    #[schema(example = "9c1f0e2a")]
+    pub kid: String,
+    #[schema(example = "ES256")]
+    pub alg: String,
+    #[serde(rename = "use")]
+    #[schema(example = "sig")]
+    pub use_: String,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl KeyRing {
+    /// Builds the ring from the active key `QR_PRIVATE_KEY_HEX` (with its ID
+    /// from `QR_KEY_ID`) and the verification-only keys in `QR_PREVIOUS_KEYS`,
+    /// a comma-separated list of `kid:hex[:YYYY-MM-DD]` entries. `hex` is
+    /// either a 32-byte private key or a 65-byte uncompressed public key; the
+    /// optional date is the last day on which the key is accepted.
+    pub fn load(
+        private_key_hex: &str,
+        key_id: Option<&str>,
+        previous_keys: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let signing_key = parse_signing_key(private_key_hex)
+            .map_err(|e| format!("QR_PRIVATE_KEY_HEX is invalid: {e}"))?;
+        let verifying_key = *signing_key.verifying_key();
+        let kid = key_id
+            .filter(|kid| !kid.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| derive_kid(&verifying_key));
+
+        let mut keys = vec![QrKey {
+            kid,
+            verifying_key,
+            active: true,
+            not_after: None,
+        }];
+
+        for entry in previous_keys
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let key = parse_previous_key(entry)
+                .map_err(|e| format!("invalid QR_PREVIOUS_KEYS entry '{entry}': {e}"))?;
+            if keys.iter().any(|k| k.kid == key.kid) {
+                return Err(format!("duplicate QR key id '{}'", key.kid).into());
+            }
+            keys.push(key);
+        }
+
+        Ok(Self { signing_key, keys })
+    }
+
+    pub fn active(&self) -> &QrKey {
+        &self.keys[0]
+    }
+
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    /// Keys that are currently accepted for verification.
+    pub fn valid_keys(&self) -> impl Iterator<Item = &QrKey> {
+        let now = Utc::now();
+        self.keys.iter().filter(move |k| k.is_valid_at(now))
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.valid_keys().map(QrKey::to_jwk).collect(),
+        }
+    }
+
+    pub fn key_set(&self) -> KeySetResponse {
+        KeySetResponse {
+            keys: self
+                .valid_keys()
+                .map(|k| PublicKeyInfo {
+                    kid: k.kid.clone(),
+                    public_key: k.public_key_hex(),
+                    active: k.active,
+                    not_after: k.not_after,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl KeyResolver for KeyRing {
+    fn resolve(&self, kid: Option<&str>) -> Vec<VerifyingKey> {
+        self.valid_keys()
+            .filter(|k| kid.is_none_or(|kid| k.kid == kid))
+            .map(|k| k.verifying_key)
+            .collect()
+    }
+}
+
+fn parse_signing_key(key_hex: &str) -> Result<SigningKey, Box<dyn std::error::Error>> {
+    let key_bytes = hex::decode(key_hex.trim())?;
+    let arr: [u8; 32] = key_bytes
+        .try_into()
+        .map_err(|_| "private key must decode to 32 bytes")?;
+    Ok(SigningKey::from_bytes((&arr).into())?)
+}
+
+fn parse_previous_key(entry: &str) -> Result<QrKey, Box<dyn std::error::Error>> {
+    let mut parts = entry.split(':');
+    let kid = parts
+        .next()
+        .filter(|k| !k.is_empty())
+        .ok_or("missing kid")?;
+    let key_hex = parts.next().ok_or("missing key")?;
+    let not_after = parts
+        .next()
+        .map(|date| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(|d| d.and_hms_opt(23, 59, 59).unwrap().and_utc())
+        })
+        .transpose()?;
+    if parts.next().is_some() {
+        return Err("too many fields".into());
+    }
+
+    let key_bytes = hex::decode(key_hex)?;
+    let verifying_key = if key_bytes.len() == 32 {
+        *parse_signing_key(key_hex)?.verifying_key()
+    } else {
+        VerifyingKey::from_sec1_bytes(&key_bytes)?
+    };
+
+    Ok(QrKey {
+        kid: kid.to_string(),
+        verifying_key,
+        active: false,
+        not_after,
+    })
+}
+
+/// Short, stable key ID: the first four bytes of the SHA-256 of the SEC1 point.
+fn derive_kid(key: &VerifyingKey) -> String {
+    let digest = Sha256::digest(key.to_encoded_point(false).as_bytes());
+    hex::encode(&digest[..4])
+}
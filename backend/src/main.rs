@@ -1,22 +1,66 @@
+mod apns;
+mod assets;
+mod card;
+mod config;
+mod error;
+mod google_wallet;
+mod i18n;
+mod issuances;
+mod jwks;
+mod keys;
+mod package;
 mod passes;
+mod passkit;
+mod policy;
+mod qr_image;
+mod revocation;
+mod semester;
+mod store;
+mod template;
 mod utils;
 use actix_governor::{Governor, GovernorConfigBuilder};
+use actix_web::http::header::{Accept, CacheControl, CacheDirective, Header};
 use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, web};
 use dotenv::dotenv;
-use log::error;
+use log::{error, warn};
 use serde::Deserialize;
 
-use utils::{log_public_key, public_key_hex};
+use assets::{Assets, Material};
+use config::Config;
+use error::{Error, Problem};
+use i18n::Language;
+use issuances::RevokeIssuanceRequest;
+use jwks::JwksCache;
+use keys::{Jwk, JwkSet, KeyRing, KeySetResponse, PublicKeyInfo};
+use passkit::{LogRequest, RegistrationRequest, SerialNumbers};
+use policy::Policy;
+use qr_image::{ImageQuery, QrImage};
+use revocation::RevokeRequest;
+use store::{Issuance, Revocation, Store};
+use utils::{Claims, authenticate, log_public_key};
 
+use passes::generate_card;
 use passes::generate_gpass;
 use passes::generate_pkpass;
 
-use utils::{QrResponse, generate_qr};
+use member_id_verify::QrPayload;
+use utils::{QrResponse, Verdict, VerifyRequest, VerifyResponse, generate_qr, verify_qr};
 
 #[derive(Deserialize, utoipa::ToSchema)]
 struct TokenQuery {
     #[schema(example = "abc123")]
     token: String,
+    #[schema(example = "en")]
+    lang: Option<String>,
+}
+
+impl TokenQuery {
+    /// Lets the `lang` parameter override the language from the token.
+    fn apply_language(&self, claims: &mut Claims) {
+        if let Some(language) = self.lang.as_deref().and_then(Language::from_tag) {
+            claims.language = language;
+        }
+    }
 }
 
 use utoipa::OpenApi;
@@ -30,67 +74,231 @@ use utoipa_swagger_ui::SwaggerUi;
     ),
     responses(
         (status = 200, description = "QR code generated successfully with issue and expiration timestamps", body = QrResponse),
-        (status = 400, description = "Bad request")
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Denied by the authorization policy", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Token lacks a required claim", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "SSO unavailable", body = Problem, content_type = "application/problem+json")
+    )
+)]
+async fn qr_endpoint(
+    req: HttpRequest,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    jwks: web::Data<JwksCache>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let qr_response = issue_app_qr(&req, &store, &policy, &jwks, &assets).await?;
+    Ok(HttpResponse::Ok().json(qr_response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/qr.png",
+    params(
+        ("Authorization" = String, Header, description = "Bearer token"),
+        ("size" = Option<u32>, Query, description = "Pixels per module, 1 to 32 (default 8)"),
+        ("ecc" = Option<String>, Query, description = "Error correction level `L`, `M`, `Q` or `H` (default `M`)"),
+        ("quiet_zone" = Option<u32>, Query, description = "Quiet zone in modules, 0 to 16 (default 4)")
+    ),
+    responses(
+        (status = 200, description = "The QR code of `/qr` as PNG image", content_type = "image/png"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Denied by the authorization policy", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid image options or token lacks a required claim", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "SSO unavailable", body = Problem, content_type = "application/problem+json")
+    )
+)]
+async fn qr_png_endpoint(
+    req: HttpRequest,
+    query: web::Query<ImageQuery>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    jwks: web::Data<JwksCache>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let qr_response = issue_app_qr(&req, &store, &policy, &jwks, &assets).await?;
+    let image = QrImage::new(&qr_response.qr, &query)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(image.png()?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/qr.svg",
+    params(
+        ("Authorization" = String, Header, description = "Bearer token"),
+        ("size" = Option<u32>, Query, description = "Pixels per module, 1 to 32 (default 8)"),
+        ("ecc" = Option<String>, Query, description = "Error correction level `L`, `M`, `Q` or `H` (default `M`)"),
+        ("quiet_zone" = Option<u32>, Query, description = "Quiet zone in modules, 0 to 16 (default 4)")
+    ),
+    responses(
+        (status = 200, description = "The QR code of `/qr` as SVG image", content_type = "image/svg+xml"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Denied by the authorization policy", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid image options or token lacks a required claim", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "SSO unavailable", body = Problem, content_type = "application/problem+json")
     )
 )]
-async fn qr_endpoint(req: HttpRequest) -> impl Responder {
+async fn qr_svg_endpoint(
+    req: HttpRequest,
+    query: web::Query<ImageQuery>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    jwks: web::Data<JwksCache>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let qr_response = issue_app_qr(&req, &store, &policy, &jwks, &assets).await?;
+    let image = QrImage::new(&qr_response.qr, &query)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(image.svg()))
+}
+
+/// Issues the app QR code of `/qr` to the holder of the bearer token. Wallet
+/// passes are only refreshed by `/pkpass` and `/gpass`, so polling the code
+/// stays free of database writes besides the issuance and of pushes.
+async fn issue_app_qr(
+    req: &HttpRequest,
+    store: &Store,
+    policy: &Policy,
+    jwks: &JwksCache,
+    assets: &Assets,
+) -> Result<QrResponse, Error> {
     const MAX_AGE_APP: u64 = 60 * 60 * 24 * 3; // 3 days
-    let token = match extract_token(&req) {
-        Ok(t) => t,
-        Err(resp) => return resp,
-    };
-    match generate_qr(&token, "a", MAX_AGE_APP).await {
-        Ok(qr_response) => HttpResponse::Ok().json(qr_response),
-        Err(e) => {
-            error!("QR generation error: {e}");
-            HttpResponse::BadRequest().body("Invalid request")
-        }
-    }
+    let token = extract_token(req)?;
+    let claims = authorize_token(&token, jwks, policy, "qr", Some("a")).await?;
+    generate_qr(&claims, "a", MAX_AGE_APP, store, &assets.current().qr)
 }
 
 #[utoipa::path(
     get,
     path = "/pkpass",
     params(
-        ("token" = String, Query, description = "Authentication token")
+        ("token" = String, Query, description = "Authentication token"),
+        ("lang" = Option<String>, Query, description = "Pass language (`de` or `en`), overrides the token's `locale` claim")
     ),
     responses(
         (status = 200, description = "PKPass generated successfully", content_type = "application/vnd.apple.pkpass"),
-        (status = 400, description = "Bad request")
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Denied by the authorization policy", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Apple Wallet passes are not configured", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Token lacks a required claim", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "SSO unavailable", body = Problem, content_type = "application/problem+json")
     )
 )]
-async fn pkpass_endpoint(query: web::Query<TokenQuery>) -> impl Responder {
-    match generate_pkpass(&query.token).await {
-        Ok(data) => HttpResponse::Ok()
-            .content_type("application/vnd.apple.pkpass")
-            .append_header(("Content-Disposition", "attachment; filename=member.pkpass"))
-            .body(data),
-        Err(e) => {
-            error!("PKPASS generation error: {e}");
-            HttpResponse::BadRequest().body("Invalid request")
-        }
-    }
+async fn pkpass_endpoint(
+    query: web::Query<TokenQuery>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    jwks: web::Data<JwksCache>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let mut claims = authorize_token(&query.token, &jwks, &policy, "pkpass", Some("wi")).await?;
+    query.apply_language(&mut claims);
+    google_wallet::refresh_holder(&store, &policy, &assets, &claims)?;
+    let material = assets.current();
+    let web_service = passkit::register_holder(&store, &material, &claims)?;
+    let data = generate_pkpass(&claims, &store, &material, web_service, false).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/vnd.apple.pkpass")
+        .append_header(("Content-Disposition", "attachment; filename=member.pkpass"))
+        .body(data))
 }
 
 #[utoipa::path(
     get,
     path = "/gpass",
     params(
-        ("token" = String, Query, description = "Authentication token")
+        ("token" = String, Query, description = "Authentication token"),
+        ("lang" = Option<String>, Query, description = "Pass language (`de` or `en`), overrides the token's `locale` claim")
     ),
     responses(
         (status = 200, description = "Google Wallet pass jwt", body = String),
-        (status = 400, description = "Bad request")
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Denied by the authorization policy", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Google Wallet passes are not configured", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Token lacks a required claim", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "SSO unavailable", body = Problem, content_type = "application/problem+json")
     )
 )]
-async fn gpass_endpoint(query: web::Query<TokenQuery>) -> impl Responder {
-    match generate_gpass(&query.token).await {
-        Ok(url) => HttpResponse::Ok().body(url),
-        Err(e) => {
-            error!("GPASS generation error: {e}");
-            HttpResponse::BadRequest().body("Invalid request")
-        }
-    }
+async fn gpass_endpoint(
+    query: web::Query<TokenQuery>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    jwks: web::Data<JwksCache>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let mut claims = authorize_token(&query.token, &jwks, &policy, "gpass", Some("wa")).await?;
+    query.apply_language(&mut claims);
+    passkit::refresh_holder(&store, &policy, &assets, &claims)?;
+    let material = assets.current();
+    let (jwt, object) = generate_gpass(&claims, &store, &material).await?;
+    google_wallet::register_object(&store, &material, &claims, object)?;
+    Ok(HttpResponse::Ok().body(jwt))
+}
+
+#[utoipa::path(
+    get,
+    path = "/card.pdf",
+    params(
+        ("token" = String, Query, description = "Authentication token"),
+        ("lang" = Option<String>, Query, description = "Card language (`de` or `en`), overrides the token's `locale` claim")
+    ),
+    responses(
+        (status = 200, description = "Printable ID-1 card with a QR code valid until the end of the semester", content_type = "application/pdf"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Denied by the authorization policy", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Printable cards are not configured", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Token lacks a required claim", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "SSO unavailable", body = Problem, content_type = "application/problem+json")
+    )
+)]
+async fn card_endpoint(
+    query: web::Query<TokenQuery>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    jwks: web::Data<JwksCache>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let mut claims = authorize_token(&query.token, &jwks, &policy, "card", Some("t")).await?;
+    query.apply_language(&mut claims);
+    let data = generate_card(&claims, &store, &assets.current())?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .append_header((
+            "Content-Disposition",
+            "attachment; filename=member-card.pdf",
+        ))
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(data))
+}
+
+#[utoipa::path(
+    post,
+    path = "/verify",
+    request_body = VerifyRequest,
+    responses(
+        (status = 200, description = "Verification verdict with the decoded payload", body = VerifyResponse),
+        (status = 422, description = "Body is not a verification request", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+async fn verify_endpoint(
+    body: web::Json<VerifyRequest>,
+    store: web::Data<Store>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let verdict = verify_qr(&body.qr, &store, &assets.current().qr)?;
+    Ok(HttpResponse::Ok().json(verdict))
 }
 
 #[utoipa::path(
@@ -104,41 +312,207 @@ async fn health() -> impl Responder {
     HttpResponse::Ok().body("OK")
 }
 
+const JWK_CONTENT_TYPE: &str = "application/jwk+json";
+const JWK_SET_CONTENT_TYPE: &str = "application/jwk-set+json";
+const PEM_CONTENT_TYPE: &str = "application/x-pem-file";
+
+enum KeyFormat {
+    Hex,
+    Jwk,
+    Pem,
+}
+
+/// Picks the best supported key format from the `Accept` header, falling back
+/// to the raw hex encoding.
+fn negotiate_key_format(req: &HttpRequest) -> KeyFormat {
+    let Ok(accept) = Accept::parse(req) else {
+        return KeyFormat::Hex;
+    };
+    accept
+        .ranked()
+        .iter()
+        .find_map(|mime| match mime.essence_str() {
+            JWK_CONTENT_TYPE | "application/json" => Some(KeyFormat::Jwk),
+            PEM_CONTENT_TYPE | "application/x-pem" => Some(KeyFormat::Pem),
+            "text/plain" => Some(KeyFormat::Hex),
+            _ => None,
+        })
+        .unwrap_or(KeyFormat::Hex)
+}
+
+fn public_key_response(key_ring: &KeyRing, format: KeyFormat) -> Result<HttpResponse, Error> {
+    let key = key_ring.active();
+    Ok(match format {
+        KeyFormat::Hex => HttpResponse::Ok()
+            .content_type("text/plain")
+            .body(key.public_key_hex()),
+        KeyFormat::Jwk => HttpResponse::Ok()
+            .content_type(JWK_CONTENT_TYPE)
+            .json(key.to_jwk()),
+        KeyFormat::Pem => HttpResponse::Ok().content_type(PEM_CONTENT_TYPE).body(
+            key.public_key_pem()
+                .map_err(|e| Error::Internal(format!("QR keys: {e}")))?,
+        ),
+    })
+}
+
 #[utoipa::path(
     get,
     path = "/public-key",
+    params(
+        ("Accept" = Option<String>, Header, description = "`text/plain` (default), `application/jwk+json` or `application/x-pem-file`")
+    ),
     responses(
-        (status = 200, description = "Public key in hex format", body = String)
+        (status = 200, description = "Active public key as hex-encoded SEC1 point, EC JWK or PEM SubjectPublicKeyInfo", body = String),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
     )
 )]
-async fn public_key_endpoint() -> impl Responder {
-    match public_key_hex() {
-        Ok(hex) => HttpResponse::Ok().body(hex),
-        Err(e) => {
-            error!("Public key error: {e}");
-            HttpResponse::InternalServerError().body("Internal server error")
-        }
-    }
+async fn public_key_endpoint(
+    req: HttpRequest,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    public_key_response(&assets.current().qr.key_ring, negotiate_key_format(&req))
+}
+
+#[utoipa::path(
+    get,
+    path = "/public-key.jwk",
+    responses(
+        (status = 200, description = "Active public key as EC JWK", body = Jwk, content_type = "application/jwk+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+async fn public_key_jwk_endpoint(assets: web::Data<Assets>) -> Result<HttpResponse, Error> {
+    public_key_response(&assets.current().qr.key_ring, KeyFormat::Jwk)
+}
+
+#[utoipa::path(
+    get,
+    path = "/public-key.pem",
+    responses(
+        (status = 200, description = "Active public key as PEM SubjectPublicKeyInfo", body = String, content_type = "application/x-pem-file"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+async fn public_key_pem_endpoint(assets: web::Data<Assets>) -> Result<HttpResponse, Error> {
+    public_key_response(&assets.current().qr.key_ring, KeyFormat::Pem)
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "All public keys currently accepted for verification as JWKS", body = JwkSet, content_type = "application/jwk-set+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+async fn jwks_endpoint(assets: web::Data<Assets>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok()
+        .content_type(JWK_SET_CONTENT_TYPE)
+        .json(assets.current().qr.key_ring.jwks()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/public-keys",
+    responses(
+        (status = 200, description = "All public keys currently accepted for verification", body = KeySetResponse),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+async fn public_keys_endpoint(assets: web::Data<Assets>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(assets.current().qr.key_ring.key_set()))
 }
 
-fn extract_token(req: &HttpRequest) -> Result<String, HttpResponse> {
+fn extract_token(req: &HttpRequest) -> Result<String, Error> {
     let auth = req
         .headers()
         .get("Authorization")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| HttpResponse::BadRequest().body("Missing Authorization header"))?;
-    if let Some(token) = auth.strip_prefix("Bearer ") {
-        Ok(token.to_string())
-    } else {
-        Err(HttpResponse::BadRequest().body("Invalid Authorization header"))
-    }
+        .ok_or_else(|| Error::Unauthorized("missing Authorization header".into()))?;
+    auth.strip_prefix("Bearer ")
+        .map(str::to_string)
+        .ok_or_else(|| Error::Unauthorized("Authorization header is not a bearer token".into()))
+}
+
+/// Verifies `token` and checks it against the policy of `endpoint` and, if
+/// given, the credential type about to be issued.
+async fn authorize_token(
+    token: &str,
+    jwks: &JwksCache,
+    policy: &Policy,
+    endpoint: &str,
+    credential: Option<&str>,
+) -> Result<Claims, Error> {
+    let claims = authenticate(token, jwks, policy).await?;
+    policy.authorize(endpoint, credential, &claims)?;
+    Ok(claims)
+}
+
+/// Verifies the bearer token and checks it against the policy of the
+/// `admin` endpoint (by default membership in `ADMIN_GROUP`).
+pub(crate) async fn require_admin(req: &HttpRequest) -> Result<Claims, Error> {
+    let token = extract_token(req)?;
+    let (Some(jwks), Some(policy)) = (
+        req.app_data::<web::Data<JwksCache>>(),
+        req.app_data::<web::Data<Policy>>(),
+    ) else {
+        return Err(Error::Internal("app state not configured".into()));
+    };
+    authorize_token(&token, jwks, policy, "admin", None).await
 }
 
 // Define OpenAPI documentation
 #[derive(OpenApi)]
 #[openapi(
-    paths(qr_endpoint, pkpass_endpoint, gpass_endpoint, health, public_key_endpoint),
-    components(schemas(TokenQuery, QrResponse)),
+    paths(
+        qr_endpoint,
+        qr_png_endpoint,
+        qr_svg_endpoint,
+        pkpass_endpoint,
+        gpass_endpoint,
+        card_endpoint,
+        verify_endpoint,
+        health,
+        public_key_endpoint,
+        public_key_jwk_endpoint,
+        public_key_pem_endpoint,
+        public_keys_endpoint,
+        jwks_endpoint,
+        revocation::signed_revocation_list,
+        revocation::list_revocations,
+        revocation::revoke,
+        revocation::unrevoke,
+        issuances::list_issuances,
+        issuances::revoke_issuance,
+        issuances::unrevoke_issuance,
+        assets::reload,
+        passkit::register_device,
+        passkit::unregister_device,
+        passkit::updated_serials,
+        passkit::latest_pass,
+        passkit::log
+    ),
+    components(schemas(
+        Problem,
+        TokenQuery,
+        QrResponse,
+        QrPayload,
+        VerifyRequest,
+        VerifyResponse,
+        Verdict,
+        KeySetResponse,
+        PublicKeyInfo,
+        Jwk,
+        JwkSet,
+        Revocation,
+        RevokeRequest,
+        Issuance,
+        RevokeIssuanceRequest,
+        RegistrationRequest,
+        SerialNumbers,
+        LogRequest
+    )),
     tags(
         (name = "Member-ID API", description = "Member ID API endpoints")
     ),
@@ -158,9 +532,48 @@ async fn main() -> std::io::Result<()> {
 
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
 
-    if let Err(e) = log_public_key() {
-        error!("Failed to derive public key: {e}");
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{e}");
+            return Err(std::io::Error::other("invalid configuration"));
+        }
+    };
+
+    log_public_key(&config.qr.key_ring);
+
+    if config.sso.issuer_url.is_none() {
+        warn!("OIDC_ISSUER_URL is not set; the iss claim of SSO tokens is not checked");
     }
+    let jwks = web::Data::new(JwksCache::new(config.sso.clone()));
+    JwksCache::spawn_background_refresh(jwks.clone().into_inner());
+
+    let policy = match Policy::load(config.policy_path.as_deref(), config.admin_group.as_deref()) {
+        Ok(policy) => web::Data::new(policy),
+        Err(e) => {
+            error!("{e}");
+            return Err(std::io::Error::other(e.to_string()));
+        }
+    };
+
+    let store = match Store::open(&config.database_path) {
+        Ok(store) => web::Data::new(store),
+        Err(e) => {
+            error!("Failed to open database {}: {e}", config.database_path);
+            return Err(std::io::Error::other(e.to_string()));
+        }
+    };
+    let assets = match Material::load(config) {
+        Ok(material) => web::Data::new(Assets::new(material)),
+        Err(e) => {
+            error!("{e}");
+            return Err(std::io::Error::other(e.to_string()));
+        }
+    };
+    #[cfg(unix)]
+    Assets::spawn_reload_on_sighup(assets.clone().into_inner())?;
+    passkit::spawn_semester_rollover(store.clone(), assets.clone());
+    google_wallet::spawn_semester_rollover(store.clone(), policy.clone(), assets.clone());
 
     let governor_conf = GovernorConfigBuilder::default()
         .requests_per_second(10)
@@ -171,10 +584,54 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(move || {
         App::new()
             .wrap(Governor::new(&governor_conf))
+            .app_data(store.clone())
+            .app_data(policy.clone())
+            .app_data(assets.clone())
+            .app_data(jwks.clone())
+            .app_data(web::JsonConfig::default().error_handler(|e, _| {
+                Error::Unprocessable(format!("invalid JSON body: {e}")).into()
+            }))
+            .app_data(
+                web::QueryConfig::default().error_handler(|e, _| {
+                    Error::Unprocessable(format!("invalid query: {e}")).into()
+                }),
+            )
             .route("/qr", web::get().to(qr_endpoint))
+            .route("/qr.png", web::get().to(qr_png_endpoint))
+            .route("/qr.svg", web::get().to(qr_svg_endpoint))
             .route("/pkpass", web::get().to(pkpass_endpoint))
             .route("/gpass", web::get().to(gpass_endpoint))
+            .route("/card.pdf", web::get().to(card_endpoint))
+            .route("/verify", web::post().to(verify_endpoint))
             .route("/public-key", web::get().to(public_key_endpoint))
+            .route("/public-key.jwk", web::get().to(public_key_jwk_endpoint))
+            .route("/public-key.pem", web::get().to(public_key_pem_endpoint))
+            .route("/public-keys", web::get().to(public_keys_endpoint))
+            .route("/.well-known/jwks.json", web::get().to(jwks_endpoint))
+            .route(
+                "/revocations",
+                web::get().to(revocation::signed_revocation_list),
+            )
+            .route(
+                "/admin/revocations",
+                web::get().to(revocation::list_revocations),
+            )
+            .route("/admin/revocations", web::post().to(revocation::revoke))
+            .route(
+                "/admin/revocations/{sub}",
+                web::delete().to(revocation::unrevoke),
+            )
+            .route("/admin/issuances", web::get().to(issuances::list_issuances))
+            .route(
+                "/admin/issuances/{jti}/revocation",
+                web::post().to(issuances::revoke_issuance),
+            )
+            .route(
+                "/admin/issuances/{jti}/revocation",
+                web::delete().to(issuances::unrevoke_issuance),
+            )
+            .route("/admin/reload", web::post().to(assets::reload))
+            .service(web::scope("/passkit/v1").configure(passkit::configure))
             .route("/health", web::get().to(health))
             .service(
                 SwaggerUi::new("/api/swagger-ui/{_:.*}")
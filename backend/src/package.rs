@@ -0,0 +1,54 @@
+use openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use openssl::sha::sha1;
+use openssl::stack::Stack;
+use passes::Package;
+use passes::sign::SignConfig;
+use serde_json::{Map, Value};
+use std::io::{Cursor, Write};
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+/// Writes a signed `.pkpass` like [`Package::write`], with additional files
+/// such as `de.lproj/pass.strings` that the `passes` crate cannot add.
+pub fn write_pkpass(
+    package: &Package,
+    files: &[(String, Vec<u8>)],
+    sign_config: &SignConfig,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut entries = vec![(
+        "pass.json".to_string(),
+        package.pass.make_json()?.into_bytes(),
+    )];
+    for resource in &package.resources {
+        entries.push((resource.filename(), resource.as_bytes().to_vec()));
+    }
+    entries.extend(files.iter().cloned());
+
+    let manifest: Map<String, Value> = entries
+        .iter()
+        .map(|(path, data)| (path.clone(), hex::encode(sha1(data)).into()))
+        .collect();
+    let manifest = serde_json::to_vec(&manifest)?;
+
+    let mut certs = Stack::new()?;
+    certs.push(sign_config.cert.clone())?;
+    let signature = Pkcs7::sign(
+        &sign_config.sign_cert,
+        &sign_config.sign_key,
+        &certs,
+        &manifest,
+        Pkcs7Flags::DETACHED,
+    )?
+    .to_der()?;
+
+    entries.push(("manifest.json".into(), manifest));
+    entries.push(("signature".into(), signature));
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (path, data) in &entries {
+        zip.start_file(path, options)?;
+        zip.write_all(data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
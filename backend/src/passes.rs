@@ -1,27 +1,31 @@
+use crate::assets::Material;
+use crate::card::{CardContent, write_card};
+use crate::config::GoogleConfig;
+use crate::error::Error;
+use crate::i18n::{self, Language, Localized, PassStrings};
+use crate::package::write_pkpass;
+use crate::semester::Semester;
+use crate::store::Store;
+use crate::template::{Design, Field, GoogleTemplate, Rgb, Text};
 use crate::utils::filter_groups;
-use crate::utils::{Claims, capitalize_groups, current_semester, generate_qr, verify_token};
+use crate::utils::{Claims, capitalize_groups, generate_qr};
 use chrono::Utc;
 use google_walletobjects1::api::{
-    Barcode as GBarcode, CardRowTemplateInfo, CardRowTwoItems, CardTemplateOverride,
-    ClassTemplateInfo, DateTime, FieldReference, FieldSelector, GenericClass, GenericObject, Image,
-    ImageUri, LocalizedString, TemplateItem, TextModuleData, TimeInterval, TranslatedString,
+    Barcode as GBarcode, CardRowOneItem, CardRowTemplateInfo, CardRowThreeItems, CardRowTwoItems,
+    CardTemplateOverride, ClassTemplateInfo, DateTime, FieldReference, FieldSelector, GenericClass,
+    GenericObject, Image, ImageUri, LocalizedString, TemplateItem, TextModuleData, TimeInterval,
+    TranslatedString,
 };
 use log::debug;
 use passes::beacon;
-use passes::sign;
 use passes::visual_appearance;
+use passes::web_service::WebService;
 use passes::{
     Package, PassBuilder, PassConfig,
     barcode::{Barcode, BarcodeFormat},
     fields::{self, Content, ContentOptions, Type as FieldType},
-    resource,
-    sign::WWDR,
 };
 use serde_json::json;
-use std::env;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
 
 fn remove_nulls(value: &mut serde_json::Value) {
     match value {
@@ -46,63 +50,24 @@ fn remove_nulls(value: &mut serde_json::Value) {
     }
 }
 
-pub async fn generate_pkpass(token: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let token_data = verify_token::<Claims>(token).await?;
-
-    if !token_data.claims.groups.iter().any(|g| g == "mitglieder") {
-        return Err("token missing required 'mitglieder' group".into());
-    }
-
-    let (semester_name, semester_end, semester_name_long) = current_semester();
-    let max_age_wallet = (semester_end.timestamp() - Utc::now().timestamp()) as u64;
-
-    let qr = generate_qr(token, "wi", max_age_wallet).await?.qr;
-
-    let organization_name = env::var("PKPASS_ORGANIZATION_NAME")?;
-    let pass_type_identifier = env::var("PKPASS_PASS_TYPE_IDENTIFIER")?;
-    let team_identifier = env::var("PKPASS_TEAM_IDENTIFIER")?;
-    let cert_path = env::var("PKPASS_SIGN_CERT_PATH")?;
-    let key_path = env::var("PKPASS_SIGN_KEY_PATH")?;
-    let beacon_proximity_uuid = env::var("PKPASS_BEACON_PROXIMITY_UUID")?;
-
-    let mut file_sign_cert = std::fs::File::open(std::path::Path::new(&cert_path))?;
-    let mut sign_cert_data = Vec::new();
-    std::io::Read::read_to_end(&mut file_sign_cert, &mut sign_cert_data)?;
-
-    let mut file_sign_key = std::fs::File::open(std::path::Path::new(&key_path))?;
-    let mut sign_key_data = Vec::new();
-    std::io::Read::read_to_end(&mut file_sign_key, &mut sign_key_data)?;
-
-    let expiration_date = semester_end;
-
-    let mut field_type = FieldType::Generic {
-        pass_fields: fields::Fields::default(),
-    };
-
-    field_type = field_type.add_primary_field(Content::new(
-        "name",
-        &token_data.claims.given_name,
-        ContentOptions {
-            label: Some("NAME".into()),
-            ..Default::default()
-        },
-    ));
-
-    let username = format!("@{}", token_data.claims.preferred_username.to_lowercase());
+fn pkpass_error(e: impl std::fmt::Display) -> Error {
+    Error::Internal(format!("failed to build pkpass: {e}"))
+}
 
-    field_type = field_type.add_secondary_field(Content::new(
-        "username",
-        &username,
-        ContentOptions {
-            label: Some("BENUTZERNAME".into()),
-            ..Default::default()
-        },
-    ));
+fn json_error(e: serde_json::Error) -> Error {
+    Error::Internal(format!("failed to serialize Google Wallet pass: {e}"))
+}
 
-    let groups: Vec<String> = token_data.claims.groups.clone();
-    let capitalized_groups = capitalize_groups(&groups);
-    let front_groups = filter_groups(&capitalized_groups);
+/// Adds a field to one section of an Apple Wallet pass.
+type AddField = fn(FieldType, Content) -> FieldType;
 
+/// Values of the template placeholders for the holder of a pass.
+fn placeholders<'a>(
+    claims: &'a Claims,
+    semester: &'a Semester,
+) -> impl Fn(&str, Language) -> Option<String> + 'a {
+    let groups = capitalize_groups(&claims.groups);
+    let front_groups = filter_groups(&groups);
     let groups_label = if front_groups.len() > 3 {
         let first_groups = front_groups[..3].join(", ");
         let remaining = front_groups.len() - 3;
@@ -110,362 +75,386 @@ pub async fn generate_pkpass(token: &str) -> Result<Vec<u8>, Box<dyn std::error:
     } else {
         front_groups.join(", ")
     };
+    let groups = groups.join(", ");
+    let front_groups = front_groups.join(", ");
+    let grace_days = semester.grace_days();
+
+    move |name, language| {
+        Some(match name {
+            "given_name" => claims.given_name.clone(),
+            "username" => claims.preferred_username.to_lowercase(),
+            "sub" => claims.sub.clone(),
+            "groups" => groups.clone(),
+            "front_groups" => front_groups.clone(),
+            "groups_label" => groups_label.clone(),
+            "semester" => semester.name.clone(),
+            "semester_long" => semester.long_name.get(language).into(),
+            "semester_end" => semester.end.format("%Y-%m-%d").to_string(),
+            "valid_until" => semester.valid_until.format("%Y-%m-%d").to_string(),
+            "grace_period" if grace_days > 0 => {
+                i18n::grace_period(language, grace_days, semester.end, semester.valid_until)
+            }
+            "grace_period" => String::new(),
+            _ => return None,
+        })
+    }
+}
 
-    field_type = field_type.add_auxiliary_field(Content::new(
-        "groups_label",
-        &groups_label,
-        ContentOptions {
-            label: Some("GRUPPEN".into()),
-            ..Default::default()
-        },
-    ));
-
-    field_type = field_type.add_header_field(Content::new(
-        "semester",
-        &semester_name,
-        ContentOptions {
-            label: Some("Semester".into()),
-            ..Default::default()
-        },
-    ));
+pub async fn generate_pkpass(
+    claims: &Claims,
+    store: &Store,
+    material: &Material,
+    web_service: Option<WebService>,
+    voided: bool,
+) -> Result<Vec<u8>, Error> {
+    let apple = material
+        .apple
+        .as_ref()
+        .ok_or_else(|| Error::NotFound("Apple Wallet passes are not configured".into()))?;
+    let template = &material.template;
+    let design = template.design(&claims.groups);
+    let now = Utc::now();
+    let semester = material.calendar.pass_semester(now)?;
+    let max_age_wallet = (semester.valid_until - now).num_seconds().max(0) as u64;
+    let values = placeholders(claims, &semester);
+    let mut strings = PassStrings::new(claims.language);
+
+    let organization_name = apple.config.organization_name.clone();
+    let pass_type_identifier = apple.config.pass_type_identifier.clone();
+    let team_identifier = apple.config.team_identifier.clone();
+    let beacon_proximity_uuid = apple.config.beacon_proximity_uuid.clone();
+
+    let expiration_date = semester.valid_until;
 
-    field_type = field_type.add_back_field(Content::new(
-        "description",
-        "Der digitale Mitgliedsausweis von Neuland Ingolstadt e.V.",
-        ContentOptions {
-            ..Default::default()
-        },
-    ));
-
-    field_type = field_type.add_back_field(Content::new(
-        "organization",
-        "Neuland Ingolstadt e.V.",
-        ContentOptions {
-            label: Some("Organisation".into()),
-            ..Default::default()
-        },
-    ));
-
-    field_type = field_type.add_back_field(Content::new(
-        "member_id",
-        &token_data.claims.sub,
-        ContentOptions {
-            label: Some("Mitgliedsnummer ID".into()),
-            ..Default::default()
-        },
-    ));
-
-    field_type = field_type.add_back_field(Content::new(
-        "groups",
-        &capitalized_groups.join(", "),
-        ContentOptions {
-            label: Some("Gruppen".into()),
-            ..Default::default()
-        },
-    ));
-
-    field_type = field_type.add_back_field(Content::new(
-        "semester_name_long",
-        &semester_name_long,
-        ContentOptions {
-            label: Some("Semester".into()),
-            ..Default::default()
-        },
-    ));
-
-    field_type = field_type.add_back_field(Content::new(
-        "valid_until",
-        &expiration_date.format("%Y-%m-%d").to_string(),
-        ContentOptions {
-            label: Some("Gültig bis".into()),
-            ..Default::default()
-        },
-    ));
-
-    field_type = field_type.add_back_field(Content::new(
-        "support",
-        "info@neuland-ingolstadt.de",
-        ContentOptions {
-            label: Some("Support".into()),
-            ..Default::default()
-        },
-    ));
-
-    let barcode = Barcode {
-        message: qr,
-        format: BarcodeFormat::QR,
-        alt_text: None,
-        message_encoding: "iso-8859-1".into(),
+    let mut field_type = FieldType::Generic {
+        pass_fields: fields::Fields::default(),
     };
+    let sections: [(&Vec<Field>, AddField); 5] = [
+        (&template.apple.header, FieldType::add_header_field),
+        (&template.apple.primary, FieldType::add_primary_field),
+        (&template.apple.secondary, FieldType::add_secondary_field),
+        (&template.apple.auxiliary, FieldType::add_auxiliary_field),
+        (&template.apple.back, FieldType::add_back_field),
+    ];
+    for (fields, add_field) in sections {
+        for field in fields {
+            let value = field.value.render(&values);
+            if value.get(claims.language).trim().is_empty() {
+                continue;
+            }
+            let label = field
+                .label
+                .as_ref()
+                .map(|label| strings.add(label.render(&values)));
+            field_type = add_field(
+                field_type,
+                Content::new(
+                    &field.key,
+                    &strings.add(value),
+                    ContentOptions {
+                        label,
+                        ..Default::default()
+                    },
+                ),
+            );
+        }
+    }
 
-    let pass = PassBuilder::new(PassConfig {
+    let color = |Rgb(r, g, b): Rgb| visual_appearance::Color::new(r, g, b);
+    let mut builder = PassBuilder::new(PassConfig {
         organization_name,
-        description: "Neuland Mitgliedsausweis".into(),
+        description: strings.add(template.description.render(&values)),
         pass_type_identifier,
         team_identifier,
-        serial_number: token_data.claims.sub,
+        serial_number: claims.sub.clone(),
     })
     .expiration_date(expiration_date)
     .fields(field_type)
     .set_sharing_prohibited(true)
-    .add_barcode(barcode)
-    .logo_text("Neuland Ingolstadt".into())
+    .logo_text(
+        strings.add(
+            design
+                .apple
+                .logo_text
+                .as_ref()
+                .unwrap_or(&template.apple.logo_text)
+                .render(&values),
+        ),
+    )
     .appearance(visual_appearance::VisualAppearance {
-        label_color: visual_appearance::Color::new(0, 221, 0),
-        foreground_color: visual_appearance::Color::white(),
-        background_color: visual_appearance::Color::black(),
+        label_color: color(
+            design
+                .apple
+                .label_color
+                .unwrap_or(template.apple.label_color),
+        ),
+        foreground_color: color(
+            design
+                .apple
+                .foreground_color
+                .unwrap_or(template.apple.foreground_color),
+        ),
+        background_color: color(
+            design
+                .apple
+                .background_color
+                .unwrap_or(template.apple.background_color),
+        ),
     })
-    .add_associated_store_identifier(1617096811)
-    .app_launch_url("https://web.neuland.app/member".into())
     .add_beacon(beacon::Beacon {
         proximity_uuid: beacon_proximity_uuid,
         major: Some(1),
         minor: Some(10),
-        relevant_text: Some("Willkommen bei Neuland!".to_string()),
-    })
-    .build();
-
-    let mut package = Package::new(pass);
-
-    let icon_path = "./resources/icon.png";
-    let icon_path_2x = "./resources/icon@2x.png";
-    let icon_path_3x = "./resources/icon@3x.png";
-
-    let icon_file = File::open(Path::new(&icon_path))?;
-    package.add_resource(resource::Type::Icon(resource::Version::Standard), icon_file)?;
-    let icon_file_2x = File::open(Path::new(&icon_path_2x))?;
-    package.add_resource(
-        resource::Type::Icon(resource::Version::Size2X),
-        icon_file_2x,
-    )?;
-    let icon_file_3x = File::open(Path::new(&icon_path_3x))?;
-    package.add_resource(
-        resource::Type::Icon(resource::Version::Size3X),
-        icon_file_3x,
-    )?;
-
-    let logo_path = "./resources/logo.png";
-    let logo_path_2x = "./resources/logo@2x.png";
-    let logo_path_3x = "./resources/logo@3x.png";
-
-    let logo_file = File::open(Path::new(logo_path))?;
-    package.add_resource(resource::Type::Logo(resource::Version::Standard), logo_file)?;
-    let logo_file_2x = File::open(Path::new(logo_path_2x))?;
-    package.add_resource(
-        resource::Type::Logo(resource::Version::Size2X),
-        logo_file_2x,
-    )?;
-    let logo_file_3x = File::open(Path::new(logo_path_3x))?;
-    package.add_resource(
-        resource::Type::Logo(resource::Version::Size3X),
-        logo_file_3x,
-    )?;
-
-    let mut file_sign_cert = File::open(Path::new(&cert_path))?;
-    let mut sign_cert_data = Vec::new();
-    std::io::Read::read_to_end(&mut file_sign_cert, &mut sign_cert_data)?;
-
-    let mut file_sign_key = File::open(Path::new(&key_path))?;
-    let mut sign_key_data = Vec::new();
-    std::io::Read::read_to_end(&mut file_sign_key, &mut sign_key_data)?;
-
-    let sign_config = sign::SignConfig::new(WWDR::G4, &sign_cert_data, &sign_key_data)?;
-    package.add_certificates(sign_config);
-
-    let mut cursor = std::io::Cursor::new(Vec::new());
-    package.write(&mut cursor)?;
-    debug!("PKPASS issued.");
-    Ok(cursor.into_inner())
-}
-
-pub async fn generate_gpass(token: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let token_data = verify_token::<Claims>(token).await?;
+        relevant_text: template
+            .apple
+            .relevant_text
+            .as_ref()
+            .map(|text| strings.add(text.render(&values))),
+    });
+    for id in &template.apple.associated_store_identifiers {
+        builder = builder.add_associated_store_identifier(*id);
+    }
+    if let Some(app_launch_url) = &template.apple.app_launch_url {
+        builder = builder.app_launch_url(app_launch_url.clone());
+    }
 
-    if !token_data.claims.groups.iter().any(|g| g == "mitglieder") {
-        return Err("token missing required 'mitglieder' group".into());
+    if let Some(web_service) = web_service {
+        builder = builder.web_service(web_service);
+    }
+    // A voided pass is only kept for reference and gets no new code.
+    if voided {
+        builder = builder.voided(true);
+    } else {
+        let qr = generate_qr(claims, "wi", max_age_wallet, store, &material.qr)?.qr;
+        builder = builder.add_barcode(Barcode {
+            message: qr,
+            format: BarcodeFormat::QR,
+            alt_text: None,
+            message_encoding: "iso-8859-1".into(),
+        });
     }
+    let pass = builder.build();
 
-    let (semester_name, semester_end, _semester_name_long) = current_semester();
-    let max_age_wallet = (semester_end.timestamp() - Utc::now().timestamp()) as u64;
+    let mut package = Package::new(pass);
 
-    let qr = generate_qr(token, "wa", max_age_wallet).await?.qr;
+    for (image_type, data) in apple.images(design) {
+        package
+            .add_resource(image_type.clone(), data.as_slice())
+            .map_err(pkpass_error)?;
+    }
 
-    let issuer_id = env::var("GOOGLE_WALLET_ISSUER_ID")?;
-    let class_id = env::var("GOOGLE_WALLET_CLASS_ID")?;
-    let service_account_email = env::var("GOOGLE_SERVICE_ACCOUNT_EMAIL")?;
-    let private_key_path = env::var("GOOGLE_SERVICE_ACCOUNT_KEY_PATH")?;
-    let mut private_key_file = std::fs::File::open(&private_key_path)?;
-    let mut private_key_pem = String::new();
-    private_key_file.read_to_string(&mut private_key_pem)?;
-    let logo_url = "https://id.neuland-ingolstadt.de/gpass-logo.png".to_string();
-    let hero_image_url = "https://id.neuland-ingolstadt.de/gpass-hero.png".to_string();
+    let data =
+        write_pkpass(&package, &strings.files(), &apple.sign_config()).map_err(pkpass_error)?;
+    debug!("PKPASS issued.");
+    Ok(data)
+}
 
-    let encoding_key = jsonwebtoken::EncodingKey::from_rsa_pem(private_key_pem.as_bytes())?;
+/// A member's Google Wallet object, built around a freshly issued code.
+pub struct IssuedObject {
+    pub id: String,
+    /// Full ID of the class the object belongs to.
+    pub class_id: String,
+    pub value: serde_json::Value,
+    /// `jti` of the code on the object.
+    pub jti: String,
+}
 
-    let object_id = format!(
-        "{}.{}.{}.10",
-        issuer_id, token_data.claims.sub, semester_name
-    );
+/// ID of the member's Google Wallet object; it stays the same across
+/// semesters so the object can be patched in place.
+pub fn gpass_object_id(google: &GoogleConfig, sub: &str) -> String {
+    format!("{}.{sub}", google.issuer_id)
+}
 
-    let groups = filter_groups(&capitalize_groups(&token_data.claims.groups)).join(", ");
+/// Full ID of the class for holders of `design`.
+pub fn gpass_class_id(google: &GoogleConfig, design: &Design) -> String {
+    let class_id = design.google.class_id.as_ref().unwrap_or(&google.class_id);
+    format!("{}.{class_id}", google.issuer_id)
+}
 
-    let card_title = LocalizedString {
-        default_value: Some(TranslatedString {
-            language: Some("de".into()),
-            value: Some("Neuland Ingolstadt e.V.".into()),
-            ..Default::default()
-        }),
+fn translated_string(language: Language, value: &str) -> TranslatedString {
+    TranslatedString {
+        language: Some(language.code().into()),
+        value: Some(value.into()),
         ..Default::default()
-    };
+    }
+}
 
-    let header = LocalizedString {
-        default_value: Some(TranslatedString {
-            language: Some("de".into()),
-            value: Some(token_data.claims.given_name.clone()),
-            ..Default::default()
-        }),
+/// A string in the holder's language with translations into the others,
+/// as far as they differ.
+fn localized_string(text: &Localized, language: Language) -> LocalizedString {
+    let translated_values: Vec<TranslatedString> = Language::ALL
+        .into_iter()
+        .filter(|other| text.get(*other) != text.get(language))
+        .map(|other| translated_string(other, text.get(other)))
+        .collect();
+    LocalizedString {
+        default_value: Some(translated_string(language, text.get(language))),
+        translated_values: (!translated_values.is_empty()).then_some(translated_values),
         ..Default::default()
-    };
+    }
+}
 
-    let subheader = LocalizedString {
-        default_value: Some(TranslatedString {
-            language: Some("de".into()),
-            value: Some(format!(
-                "@{}",
-                token_data.claims.preferred_username.to_lowercase()
-            )),
-            ..Default::default()
-        }),
-        ..Default::default()
-    };
+pub fn generic_object(
+    claims: &Claims,
+    store: &Store,
+    material: &Material,
+) -> Result<IssuedObject, Error> {
+    let google = material
+        .google
+        .as_ref()
+        .ok_or_else(|| Error::NotFound("Google Wallet passes are not configured".into()))?;
+    let template = &material.template.google;
+    let design = material.template.design(&claims.groups);
+    let now = Utc::now();
+    let semester = material.calendar.pass_semester(now)?;
+    let max_age_wallet = (semester.valid_until - now).num_seconds().max(0) as u64;
+    let values = placeholders(claims, &semester);
+    let language = claims.language;
+    let localized = |text: &Text| localized_string(&text.render(&values), language);
+
+    let issued = generate_qr(claims, "wa", max_age_wallet, store, &material.qr)?;
+
+    let class_id = gpass_class_id(&google.config, design);
+    let logo_url = google.config.logo_url.clone();
+
+    let object_id = gpass_object_id(&google.config, &claims.sub);
 
     let logo = Image {
         source_uri: Some(ImageUri {
             uri: Some(logo_url),
             ..Default::default()
         }),
-        content_description: Some(LocalizedString {
-            default_value: Some(TranslatedString {
-                language: Some("de".into()),
-                value: Some("Neuland Ingolstadt e.V. Logo".into()),
-                ..Default::default()
-            }),
-            ..Default::default()
-        }),
+        content_description: template.logo_description.as_ref().map(localized),
         ..Default::default()
     };
 
     let barcode = GBarcode {
         type_: Some("QR_CODE".into()),
-        value: Some(qr),
+        value: Some(issued.qr),
         ..Default::default()
     };
 
     let valid_time_interval = TimeInterval {
         start: Some(DateTime {
-            date: Some(Utc::now().to_rfc3339()),
+            date: Some(now.to_rfc3339()),
         }),
         end: Some(DateTime {
-            date: Some(semester_end.to_rfc3339()),
+            date: Some(semester.valid_until.to_rfc3339()),
         }),
         ..Default::default()
     };
 
-    let text_modules = vec![
-        TextModuleData {
-            header: Some("Name".into()),
-            body: Some(token_data.claims.given_name),
-            id: Some("NAME".into()),
-            ..Default::default()
-        },
-        TextModuleData {
-            header: Some("Benutzername".into()),
-            body: Some(token_data.claims.preferred_username.to_lowercase()),
-            id: Some("USERNAME".into()),
-            ..Default::default()
-        },
-        TextModuleData {
-            header: Some("Semester".into()),
-            body: Some(semester_name),
-            id: Some("SEMESTER".into()),
-            ..Default::default()
-        },
-        TextModuleData {
-            header: Some("Gruppen".into()),
-            body: Some(groups),
-            id: Some("GROUPS".into()),
-            ..Default::default()
-        },
-        TextModuleData {
-            header: Some("Gültig bis".into()),
-            body: Some(semester_end.format("%Y-%m-%d").to_string()),
-            id: Some("VALID_UNTIL".into()),
-            ..Default::default()
-        },
-        TextModuleData {
-            header: Some("Mitgliedsnummer".into()),
-            body: Some(token_data.claims.sub),
-            id: Some("MEMBER_ID".into()),
-            ..Default::default()
-        },
-    ];
-
-    let hero_image = Image {
+    let text_modules = template
+        .text_modules
+        .iter()
+        .filter_map(|field| {
+            let body = field.value.render(&values);
+            if body.get(language).trim().is_empty() {
+                return None;
+            }
+            let header = field.label.as_ref().map(|label| label.render(&values));
+            Some(TextModuleData {
+                header: header.as_ref().map(|header| header.get(language).into()),
+                localized_header: header
+                    .as_ref()
+                    .map(|header| localized_string(header, language)),
+                body: Some(body.get(language).into()),
+                localized_body: Some(localized_string(&body, language)),
+                id: Some(field.key.clone()),
+            })
+        })
+        .collect();
+
+    let hero_image_url = design
+        .google
+        .hero_image_url
+        .as_ref()
+        .or(template.hero_image_url.as_ref());
+    let hero_image = hero_image_url.map(|uri| Image {
         source_uri: Some(ImageUri {
-            uri: Some(hero_image_url),
+            uri: Some(uri.clone()),
             ..Default::default()
         }),
         ..Default::default()
-    };
+    });
 
     let object = GenericObject {
-        id: Some(object_id),
-        class_id: Some(format!("{issuer_id}.{class_id}")),
+        id: Some(object_id.clone()),
+        class_id: Some(class_id.clone()),
         state: Some("ACTIVE".into()),
-        card_title: Some(card_title),
-        header: Some(header),
-        hero_image: Some(hero_image),
-        subheader: Some(subheader),
+        card_title: Some(localized(&template.card_title)),
+        header: Some(localized(&template.header)),
+        hero_image,
+        subheader: template.subheader.as_ref().map(localized),
         logo: Some(logo),
-        hex_background_color: Some("#031c07".into()),
+        hex_background_color: Some(
+            design
+                .google
+                .background_color
+                .unwrap_or(template.background_color)
+                .to_string(),
+        ),
         barcode: Some(barcode),
         valid_time_interval: Some(valid_time_interval),
         text_modules_data: Some(text_modules),
         ..Default::default()
     };
 
-    let mut object_value = serde_json::to_value(object)?;
-    remove_nulls(&mut object_value);
+    let mut value = serde_json::to_value(object).map_err(json_error)?;
+    remove_nulls(&mut value);
+    Ok(IssuedObject {
+        id: object_id,
+        class_id,
+        value,
+        jti: issued.jti,
+    })
+}
 
-    let groups_selector = FieldSelector {
-        fields: Some(vec![FieldReference {
-            field_path: Some("object.textModulesData['GROUPS']".into()),
-            ..Default::default()
-        }]),
-    };
-    let semester_selector = FieldSelector {
-        fields: Some(vec![FieldReference {
-            field_path: Some("object.textModulesData['SEMESTER']".into()),
+/// A class for member objects; it lays out the card rows.
+pub fn generic_class(
+    template: &GoogleTemplate,
+    class_id: &str,
+) -> Result<serde_json::Value, Error> {
+    let item = |key: &String| {
+        Some(TemplateItem {
+            first_value: Some(FieldSelector {
+                fields: Some(vec![FieldReference {
+                    field_path: Some(format!("object.textModulesData['{key}']")),
+                    ..Default::default()
+                }]),
+            }),
             ..Default::default()
-        }]),
+        })
     };
-
-    let card_rows = vec![CardRowTemplateInfo {
-        two_items: Some(CardRowTwoItems {
-            start_item: Some(TemplateItem {
-                first_value: Some(groups_selector),
+    let card_rows = template
+        .card_rows
+        .iter()
+        .map(|row| match &row[..] {
+            [only] => CardRowTemplateInfo {
+                one_item: Some(CardRowOneItem { item: item(only) }),
                 ..Default::default()
-            }),
-            end_item: Some(TemplateItem {
-                first_value: Some(semester_selector),
+            },
+            [start, end] => CardRowTemplateInfo {
+                two_items: Some(CardRowTwoItems {
+                    start_item: item(start),
+                    end_item: item(end),
+                }),
                 ..Default::default()
-            }),
-        }),
-        ..Default::default()
-    }];
+            },
+            [start, middle, end, ..] => CardRowTemplateInfo {
+                three_items: Some(CardRowThreeItems {
+                    start_item: item(start),
+                    middle_item: item(middle),
+                    end_item: item(end),
+                }),
+                ..Default::default()
+            },
+            [] => CardRowTemplateInfo::default(),
+        })
+        .collect();
 
     let class = GenericClass {
-        id: Some(format!("{issuer_id}.{class_id}")),
+        id: Some(class_id.into()),
         class_template_info: Some(ClassTemplateInfo {
             card_template_override: Some(CardTemplateOverride {
                 card_row_template_infos: Some(card_rows),
@@ -475,22 +464,74 @@ pub async fn generate_gpass(token: &str) -> Result<String, Box<dyn std::error::E
         ..Default::default()
     };
 
-    let mut class_value = serde_json::to_value(class)?;
+    let mut class_value = serde_json::to_value(class).map_err(json_error)?;
     remove_nulls(&mut class_value);
+    Ok(class_value)
+}
+
+/// Builds the member's object and the "save to Google Wallet" JWT carrying
+/// it. The object is returned so it can also be stored server-side.
+pub async fn generate_gpass(
+    claims: &Claims,
+    store: &Store,
+    material: &Material,
+) -> Result<(String, IssuedObject), Error> {
+    let google = material
+        .google
+        .as_ref()
+        .ok_or_else(|| Error::NotFound("Google Wallet passes are not configured".into()))?;
+    let object = generic_object(claims, store, material)?;
+    let class_value = generic_class(&material.template.google, &object.class_id)?;
 
     let claims = json!({
-        "iss": service_account_email,
+        "iss": google.config.service_account_email,
         "iat": Utc::now().timestamp(),
         "aud": "google",
         "typ": "savetowallet",
-        "payload": {"genericObjects": [object_value], "genericClasses": [class_value]},
+        "payload": {"genericObjects": [object.value], "genericClasses": [class_value]},
     });
 
     let jwt = jsonwebtoken::encode(
         &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
         &claims,
-        &encoding_key,
-    )?;
+        &google.encoding_key,
+    )
+    .map_err(|e| Error::Internal(format!("failed to sign Google Wallet JWT: {e}")))?;
     debug!("GPASS issued.");
-    Ok(jwt)
+    Ok((jwt, object))
+}
+
+/// Renders the printable card of the holder of `claims` as PDF.
+pub fn generate_card(
+    claims: &Claims,
+    store: &Store,
+    material: &Material,
+) -> Result<Vec<u8>, Error> {
+    let logo = material.card_logo.as_ref().ok_or_else(|| {
+        Error::NotFound("printable cards are not configured, logo.png is missing".into())
+    })?;
+    let template = &material.template.card;
+    let now = Utc::now();
+    let semester = material.calendar.pass_semester(now)?;
+    let max_age_wallet = (semester.valid_until - now).num_seconds().max(0) as u64;
+    let values = placeholders(claims, &semester);
+    let render = |text: &Text| text.render(&values).get(claims.language).to_string();
+
+    let fields = template
+        .fields
+        .iter()
+        .map(|field| {
+            let label = field.label.as_ref().map(render).unwrap_or_default();
+            (label, render(&field.value))
+        })
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    let card = CardContent {
+        title: render(&template.title),
+        name: render(&template.name),
+        subtitle: template.subtitle.as_ref().map(render),
+        fields,
+        qr: generate_qr(claims, "t", max_age_wallet, store, &material.qr)?.qr,
+    };
+    write_card(&card, logo).map_err(|e| Error::Internal(format!("failed to build card: {e}")))
 }
@@ -0,0 +1,748 @@
+use actix_web::http::header::{self, Header, HttpDate, IfModifiedSince, LastModified};
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use passes::web_service::WebService;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::assets::{Assets, Material};
+use crate::error::Error;
+use crate::passes::generate_pkpass;
+use crate::policy::Policy;
+use crate::revocation::now;
+use crate::semester::Calendar;
+use crate::store::{Store, WalletPass};
+use crate::utils::Claims;
+
+/// Scheme of the `Authorization` header devices send to the PassKit web
+/// service, followed by the pass's authentication token.
+const AUTHORIZATION_SCHEME: &str = "ApplePass ";
+
+const SEMESTER_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationRequest {
+    #[schema(example = "a1b2c3d4e5f6")]
+    pub push_token: String,
+}
+
+#[derive(Deserialize, utoipa::IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedSinceQuery {
+    /// `lastUpdated` tag of a previous response.
+    pub passes_updated_since: Option<String>,
+}
+
+#[derive(Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SerialNumbers {
+    #[schema(example = json!(["3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f"]))]
+    pub serial_numbers: Vec<String>,
+    #[schema(example = "1719322624")]
+    pub last_updated: String,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct LogRequest {
+    pub logs: Vec<String>,
+}
+
+/// Routes of the PassKit web service, mounted under `/passkit/v1`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/devices/{device}/registrations/{pass_type}/{serial}",
+        web::post().to(register_device),
+    )
+    .route(
+        "/devices/{device}/registrations/{pass_type}/{serial}",
+        web::delete().to(unregister_device),
+    )
+    .route(
+        "/devices/{device}/registrations/{pass_type}",
+        web::get().to(updated_serials),
+    )
+    .route("/passes/{pass_type}/{serial}", web::get().to(latest_pass))
+    .route("/log", web::post().to(log));
+}
+
+/// Records the holder of a pass that is about to be downloaded and returns
+/// the web service entry for it, or `None` if `PKPASS_WEB_SERVICE_URL` is
+/// not set. Re-downloads keep the authentication token so other devices
+/// holding the pass stay registered.
+pub fn register_holder(
+    store: &web::Data<Store>,
+    material: &Arc<Material>,
+    claims: &Claims,
+) -> Result<Option<WebService>, Error> {
+    let Some(web_service_url) = material
+        .apple
+        .as_ref()
+        .and_then(|apple| apple.config.web_service_url.clone())
+    else {
+        return Ok(None);
+    };
+    let now = now();
+    let authentication_token = match store.wallet_pass(&claims.sub)? {
+        Some(pass) => pass.authentication_token,
+        None => hex::encode(rand::random::<[u8; 16]>()),
+    };
+    store.save_wallet_pass(&WalletPass {
+        serial_number: claims.sub.clone(),
+        authentication_token: authentication_token.clone(),
+        given_name: claims.given_name.clone(),
+        preferred_username: claims.preferred_username.clone(),
+        groups: claims.groups.clone(),
+        client: claims.azp.clone(),
+        semester: material.calendar.pass_semester(Utc::now())?.name,
+        refreshed_at: now,
+        updated_at: now,
+        language: claims.language,
+    })?;
+    push_updates(store, material, vec![claims.sub.clone()]);
+    Ok(Some(WebService {
+        authentication_token,
+        web_service_url,
+    }))
+}
+
+/// Updates the holder data of an existing pass from a fresh SSO token, so
+/// changed names or groups reach the member's devices and lapsed passes get
+/// a code again if the policy allows it.
+pub fn refresh_holder(
+    store: &web::Data<Store>,
+    policy: &Policy,
+    assets: &Assets,
+    claims: &Claims,
+) -> Result<(), Error> {
+    let Some(mut pass) = store.wallet_pass(&claims.sub)? else {
+        return Ok(());
+    };
+    let now = now();
+    let material = assets.current();
+    let was_voided = serves_voided(store, &material.calendar, policy, &pass)?;
+    let changed = pass.given_name != claims.given_name
+        || pass.preferred_username != claims.preferred_username
+        || pass.groups != claims.groups;
+    pass.given_name = claims.given_name.clone();
+    pass.preferred_username = claims.preferred_username.clone();
+    pass.groups = claims.groups.clone();
+    pass.refreshed_at = now;
+    let updated = changed || was_voided != serves_voided(store, &material.calendar, policy, &pass)?;
+    if updated {
+        pass.updated_at = now;
+    }
+    store.save_wallet_pass(&pass)?;
+    if updated {
+        push_updates(store, &material, vec![pass.serial_number]);
+    }
+    Ok(())
+}
+
+/// Tells the devices registered for `serials` to fetch their passes again.
+/// Notifications are sent in the background; results are recorded against
+/// the registrations.
+pub fn push_updates(store: &web::Data<Store>, material: &Arc<Material>, serials: Vec<String>) {
+    if serials.is_empty()
+        || material
+            .apple
+            .as_ref()
+            .is_none_or(|apple| apple.apns.is_none())
+    {
+        return;
+    }
+    let store = store.clone();
+    let material = material.clone();
+    tokio::spawn(async move {
+        if let Some(apns) = material
+            .apple
+            .as_ref()
+            .and_then(|apple| apple.apns.as_ref())
+        {
+            apns.notify(&store, &serials).await;
+        }
+    });
+}
+
+/// Marks passes built for another semester than the one passes are issued
+/// for now as changed and notifies their devices.
+fn roll_over_semester(store: &web::Data<Store>, material: &Arc<Material>) -> Result<(), Error> {
+    let semester = material.calendar.pass_semester(Utc::now())?;
+    let serials = store.roll_over_semester(&semester.name, now())?;
+    if !serials.is_empty() {
+        info!("Semester changed, updating {} passes", serials.len());
+        push_updates(store, material, serials);
+    }
+    Ok(())
+}
+
+/// Checks for a semester change every hour, so passes update even if no
+/// device asks.
+pub fn spawn_semester_rollover(store: web::Data<Store>, assets: web::Data<Assets>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SEMESTER_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = roll_over_semester(&store, &assets.current()) {
+                warn!("Semester roll-over failed: {e}");
+            }
+        }
+    });
+}
+
+/// A pass is voided while its member is revoked and has not fetched a
+/// credential with a fresh SSO token since, or while the code it shows was
+/// revoked. Only unrevoking that code or downloading the pass again, which
+/// issues a new one, brings it back.
+fn is_voided(store: &Store, pass: &WalletPass) -> Result<bool, Error> {
+    let member_revoked = store
+        .revocation(&pass.serial_number)?
+        .is_some_and(|revocation| revocation.revoked_at >= pass.refreshed_at);
+    let code_revoked = store
+        .latest_issuance(&pass.serial_number, "wi")?
+        .is_some_and(|issuance| issuance.revoked_at.is_some());
+    Ok(member_revoked || code_revoked)
+}
+
+/// Whether a pass may get a new code built from its stored holder data: the
+/// holder signed in during the previous semester or later, and the policy
+/// still grants their groups an Apple Wallet pass.
+fn is_renewable(calendar: &Calendar, policy: &Policy, pass: &WalletPass) -> Result<bool, Error> {
+    let refreshed_at = DateTime::from_timestamp(pass.refreshed_at as i64, 0).unwrap_or_default();
+    Ok(calendar.is_fresh(refreshed_at, Utc::now())?
+        && policy
+            .authorize("pkpass", Some("wi"), &holder_claims(pass))
+            .is_ok())
+}
+
+/// Whether [`latest_pass`] serves the pass voided.
+fn serves_voided(
+    store: &Store,
+    calendar: &Calendar,
+    policy: &Policy,
+    pass: &WalletPass,
+) -> Result<bool, Error> {
+    Ok(is_voided(store, pass)? || !is_renewable(calendar, policy, pass)?)
+}
+
+/// The claims a pass was last built from.
+fn holder_claims(pass: &WalletPass) -> Claims {
+    Claims {
+        sub: pass.serial_number.clone(),
+        given_name: pass.given_name.clone(),
+        preferred_username: pass.preferred_username.clone(),
+        groups: pass.groups.clone(),
+        azp: pass.client.clone(),
+        language: pass.language,
+    }
+}
+
+/// The current material, if it serves passes of `pass_type_identifier`.
+fn material_for(assets: &Assets, pass_type_identifier: &str) -> Result<Arc<Material>, Error> {
+    let material = assets.current();
+    match &material.apple {
+        Some(apple) if apple.config.pass_type_identifier == pass_type_identifier => Ok(material),
+        _ => Err(Error::NotFound(format!(
+            "unknown pass type {pass_type_identifier}"
+        ))),
+    }
+}
+
+/// Checks the `ApplePass` authorization header against the pass.
+fn authenticate_pass(
+    req: &HttpRequest,
+    store: &Store,
+    serial_number: &str,
+) -> Result<WalletPass, Error> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix(AUTHORIZATION_SCHEME))
+        .ok_or_else(|| Error::Unauthorized("missing ApplePass authorization".into()))?;
+    match store.wallet_pass(serial_number)? {
+        Some(pass) if pass.authentication_token == token => Ok(pass),
+        _ => Err(Error::Unauthorized(
+            "invalid authentication token for pass".into(),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/passkit/v1/devices/{deviceLibraryIdentifier}/registrations/{passTypeIdentifier}/{serialNumber}",
+    request_body = RegistrationRequest,
+    params(
+        ("deviceLibraryIdentifier" = String, Path, description = "Device identifier"),
+        ("passTypeIdentifier" = String, Path, description = "Pass type identifier"),
+        ("serialNumber" = String, Path, description = "Pass serial number"),
+        ("Authorization" = String, Header, description = "ApplePass <authenticationToken>")
+    ),
+    responses(
+        (status = 201, description = "Device registered"),
+        (status = 200, description = "Device was already registered"),
+        (status = 401, description = "Invalid authentication token", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown pass type", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn register_device(
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    body: web::Json<RegistrationRequest>,
+    store: web::Data<Store>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let (device, pass_type_identifier, serial_number) = path.into_inner();
+    material_for(&assets, &pass_type_identifier)?;
+    authenticate_pass(&req, &store, &serial_number)?;
+    if store.register_device(&device, &serial_number, &body.push_token, now())? {
+        info!("Registered device {device} for pass {serial_number}");
+        Ok(HttpResponse::Created().finish())
+    } else {
+        Ok(HttpResponse::Ok().finish())
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/passkit/v1/devices/{deviceLibraryIdentifier}/registrations/{passTypeIdentifier}/{serialNumber}",
+    params(
+        ("deviceLibraryIdentifier" = String, Path, description = "Device identifier"),
+        ("passTypeIdentifier" = String, Path, description = "Pass type identifier"),
+        ("serialNumber" = String, Path, description = "Pass serial number"),
+        ("Authorization" = String, Header, description = "ApplePass <authenticationToken>")
+    ),
+    responses(
+        (status = 200, description = "Device unregistered"),
+        (status = 401, description = "Invalid authentication token", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown pass type", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn unregister_device(
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    store: web::Data<Store>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let (device, pass_type_identifier, serial_number) = path.into_inner();
+    material_for(&assets, &pass_type_identifier)?;
+    authenticate_pass(&req, &store, &serial_number)?;
+    if store.unregister_device(&device, &serial_number)? {
+        info!("Unregistered device {device} from pass {serial_number}");
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/passkit/v1/devices/{deviceLibraryIdentifier}/registrations/{passTypeIdentifier}",
+    params(
+        ("deviceLibraryIdentifier" = String, Path, description = "Device identifier"),
+        ("passTypeIdentifier" = String, Path, description = "Pass type identifier"),
+        UpdatedSinceQuery
+    ),
+    responses(
+        (status = 200, description = "Serial numbers of the passes that changed since the tag", body = SerialNumbers),
+        (status = 204, description = "No pass changed"),
+        (status = 404, description = "Unknown pass type", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn updated_serials(
+    path: web::Path<(String, String)>,
+    query: web::Query<UpdatedSinceQuery>,
+    store: web::Data<Store>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let (device, pass_type_identifier) = path.into_inner();
+    let material = material_for(&assets, &pass_type_identifier)?;
+    // Unparsable tags are treated as absent, so the device gets every pass.
+    let since = query
+        .passes_updated_since
+        .as_deref()
+        .and_then(|tag| tag.parse().ok());
+
+    let now = now();
+    roll_over_semester(&store, &material)?;
+    let serials = store.updated_serials(&device, since)?;
+    let Some(last_updated) = serials.iter().map(|(_, updated_at)| *updated_at).max() else {
+        return Ok(HttpResponse::NoContent().finish());
+    };
+    // Passes changed later in this second are listed by the next request.
+    let last_updated = last_updated.max(now);
+    Ok(HttpResponse::Ok().json(SerialNumbers {
+        serial_numbers: serials.into_iter().map(|(serial, _)| serial).collect(),
+        last_updated: last_updated.to_string(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/passkit/v1/passes/{passTypeIdentifier}/{serialNumber}",
+    params(
+        ("passTypeIdentifier" = String, Path, description = "Pass type identifier"),
+        ("serialNumber" = String, Path, description = "Pass serial number"),
+        ("Authorization" = String, Header, description = "ApplePass <authenticationToken>"),
+        ("If-Modified-Since" = Option<String>, Header, description = "Last-Modified of the pass the device holds")
+    ),
+    responses(
+        (status = 200, description = "Latest version of the pass", content_type = "application/vnd.apple.pkpass"),
+        (status = 304, description = "Pass has not changed"),
+        (status = 401, description = "Invalid authentication token", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown pass type", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn latest_pass(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let (pass_type_identifier, serial_number) = path.into_inner();
+    let material = material_for(&assets, &pass_type_identifier)?;
+    roll_over_semester(&store, &material)?;
+    let pass = authenticate_pass(&req, &store, &serial_number)?;
+
+    let last_modified = UNIX_EPOCH + Duration::from_secs(pass.updated_at);
+    if let Ok(IfModifiedSince(since)) = IfModifiedSince::parse(&req)
+        && last_modified <= SystemTime::from(since)
+    {
+        return Ok(HttpResponse::NotModified().finish());
+    }
+
+    let voided = if is_voided(&store, &pass)? {
+        true
+    } else if !is_renewable(&material.calendar, &policy, &pass)? {
+        debug!("Pass {serial_number} lapsed, serving it voided");
+        true
+    } else {
+        false
+    };
+    let claims = holder_claims(&pass);
+    let web_service = material
+        .apple
+        .as_ref()
+        .and_then(|apple| apple.config.web_service_url.clone())
+        .map(|web_service_url| WebService {
+            authentication_token: pass.authentication_token,
+            web_service_url,
+        });
+    let data = generate_pkpass(&claims, &store, &material, web_service, voided).await?;
+    debug!("Served updated pass {serial_number}");
+    Ok(HttpResponse::Ok()
+        .content_type("application/vnd.apple.pkpass")
+        .insert_header(LastModified(HttpDate::from(last_modified)))
+        .body(data))
+}
+
+#[utoipa::path(
+    post,
+    path = "/passkit/v1/log",
+    request_body = LogRequest,
+    responses(
+        (status = 200, description = "Messages logged")
+    )
+)]
+pub async fn log(body: web::Json<LogRequest>) -> HttpResponse {
+    for message in &body.logs {
+        info!("PassKit: {message}");
+    }
+    HttpResponse::Ok().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::{
+        TestRequest, call_and_read_body, call_and_read_body_json, call_service, init_service,
+    };
+    use actix_web::{App, HttpServer};
+    use member_id_verify::Format;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509, X509NameBuilder};
+    use std::io::{Cursor, Read};
+    use std::net::TcpListener;
+    use std::path::Path;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::config::{AppleConfig, Config, QrConfig, SsoConfig};
+    use crate::keys::KeyRing;
+    use crate::store::Issuance;
+    use crate::template::PassTemplate;
+
+    const PASS_TYPE: &str = "pass.de.neuland.test";
+    const SERIAL: &str = "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f";
+    const TOKEN: &str = "0123456789abcdef";
+    /// APNs endpoint for tests that do not look at pushes; nothing listens.
+    const NO_APNS: &str = "http://127.0.0.1:9";
+
+    /// A push received by the mock APNs server, as `(push token, topic)`.
+    type Push = (String, String);
+
+    /// Starts an HTTP/2 server answering APNs pushes and returns its URL.
+    fn mock_apns() -> (String, mpsc::UnboundedReceiver<Push>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = HttpServer::new(move || {
+            let sender = sender.clone();
+            App::new().route(
+                "/3/device/{token}",
+                web::post().to(move |req: HttpRequest, token: web::Path<String>| {
+                    let topic = req
+                        .headers()
+                        .get("apns-topic")
+                        .and_then(|h| h.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string();
+                    let _ = sender.send((token.into_inner(), topic));
+                    async { HttpResponse::Ok().finish() }
+                }),
+            )
+        })
+        .workers(1)
+        .listen_auto_h2c(listener)
+        .unwrap()
+        .run();
+        tokio::spawn(server);
+        (url, receiver)
+    }
+
+    /// Writes a self-signed certificate and key standing in for the pass
+    /// type certificate.
+    fn signing_files() -> (String, String) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", PASS_TYPE).unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("member-id-passkit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("pass.pem");
+        let key_path = dir.join("pass.key");
+        std::fs::write(&cert_path, cert.build().to_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, key.rsa().unwrap().private_key_to_pem().unwrap()).unwrap();
+        (
+            cert_path.display().to_string(),
+            key_path.display().to_string(),
+        )
+    }
+
+    fn material(apns_url: &str) -> Material {
+        let (sign_cert_path, sign_key_path) = signing_files();
+        let material = Material::load(Config {
+            sso: SsoConfig {
+                issuer_url: None,
+                jwks_url: None,
+                expected_audience: String::new(),
+                leeway: 0,
+                jwks_cache_ttl: 0,
+                jwks_min_refetch_interval: 0,
+                jwks_max_stale: 0,
+            },
+            admin_group: None,
+            policy_path: None,
+            database_path: ":memory:".into(),
+            resources_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("resources"),
+            qr: QrConfig {
+                key_ring: KeyRing::load(&"01".repeat(32), None, "").unwrap(),
+                format: Format::default(),
+                issuer: None,
+                roles: Vec::new(),
+            },
+            calendar: calendar(),
+            template: PassTemplate::load(None).unwrap(),
+            apple: Some(AppleConfig {
+                organization_name: "Neuland".into(),
+                pass_type_identifier: PASS_TYPE.into(),
+                team_identifier: "TEAM".into(),
+                sign_cert_path: sign_cert_path.clone(),
+                sign_key_path,
+                beacon_proximity_uuid: String::new(),
+                web_service_url: Some("https://id.example.org/passkit".into()),
+                apns_url: apns_url.into(),
+            }),
+            google: None,
+        });
+        let _ = std::fs::remove_dir_all(Path::new(&sign_cert_path).parent().unwrap());
+        material.unwrap()
+    }
+
+    fn calendar() -> Calendar {
+        Calendar::load(None, 0, 0).unwrap()
+    }
+
+    fn store() -> web::Data<Store> {
+        let store = Store::open(":memory:").unwrap();
+        store
+            .save_wallet_pass(&WalletPass {
+                serial_number: SERIAL.into(),
+                authentication_token: TOKEN.into(),
+                given_name: "Max".into(),
+                preferred_username: "max".into(),
+                groups: vec!["mitglieder".into()],
+                client: None,
+                semester: calendar().pass_semester(Utc::now()).unwrap().name,
+                refreshed_at: now(),
+                updated_at: now() - 60,
+                language: Default::default(),
+            })
+            .unwrap();
+        web::Data::new(store)
+    }
+
+    fn issue(store: &Store, jti: &str, iat: u64) {
+        store
+            .record_issuance(&Issuance {
+                jti: jti.into(),
+                sub: SERIAL.into(),
+                t: "wi".into(),
+                iat,
+                exp: iat + 3600,
+                client: None,
+                revoked_at: None,
+                revoked_by: None,
+                revoke_reason: None,
+            })
+            .unwrap();
+    }
+
+    /// Fetches the pass like a registered device and returns its `pass.json`.
+    async fn fetch_pass(store: &web::Data<Store>, assets: &web::Data<Assets>) -> serde_json::Value {
+        let app = init_service(
+            App::new()
+                .app_data(store.clone())
+                .app_data(web::Data::new(Policy::default()))
+                .app_data(assets.clone())
+                .service(web::scope("/passkit/v1").configure(configure)),
+        )
+        .await;
+        let request = TestRequest::get()
+            .uri(&format!("/passkit/v1/passes/{PASS_TYPE}/{SERIAL}"))
+            .insert_header(("Authorization", format!("{AUTHORIZATION_SCHEME}{TOKEN}")))
+            .to_request();
+        let pkpass = call_and_read_body(&app, request).await;
+        let mut archive = zip::ZipArchive::new(Cursor::new(pkpass.to_vec())).unwrap();
+        let mut pass_json = String::new();
+        archive
+            .by_name("pass.json")
+            .unwrap()
+            .read_to_string(&mut pass_json)
+            .unwrap();
+        serde_json::from_str(&pass_json).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn revoking_the_shown_code_voids_the_pass() {
+        let assets = web::Data::new(Assets::new(material(NO_APNS)));
+        let store = store();
+        let pass = fetch_pass(&store, &assets).await;
+        assert_ne!(pass["voided"], true);
+        assert_eq!(pass["barcodes"].as_array().map(Vec::len), Some(1));
+
+        let shown = store.latest_issuance(SERIAL, "wi").unwrap().unwrap().jti;
+        assert!(store.revoke_issuance(&shown, now(), "admin", None).unwrap());
+        for _ in 0..2 {
+            // Serving the voided pass must not hand out a replacement code.
+            let pass = fetch_pass(&store, &assets).await;
+            assert_eq!(pass["voided"], true);
+            assert!(
+                pass.get("barcodes")
+                    .is_none_or(|b| b.as_array().unwrap().is_empty())
+            );
+        }
+
+        assert!(store.unrevoke_issuance(&shown).unwrap());
+        let pass = fetch_pass(&store, &assets).await;
+        assert_ne!(pass["voided"], true);
+        let reissued = store.latest_issuance(SERIAL, "wi").unwrap().unwrap().jti;
+        assert_ne!(reissued, shown);
+    }
+
+    #[actix_web::test]
+    async fn revoking_an_older_code_keeps_the_pass() {
+        let assets = web::Data::new(Assets::new(material(NO_APNS)));
+        let store = store();
+        issue(&store, "old", now() - 120);
+        issue(&store, "shown", now() - 60);
+        assert!(store.revoke_issuance("old", now(), "admin", None).unwrap());
+        assert_ne!(fetch_pass(&store, &assets).await["voided"], true);
+    }
+
+    #[test]
+    fn lists_passes_changed_in_the_second_of_the_last_tag() {
+        let store = store();
+        assert!(
+            store
+                .register_device("device-1", SERIAL, "push-1", now())
+                .unwrap()
+        );
+        let tag = now();
+        assert!(store.touch_wallet_pass(SERIAL, tag).unwrap());
+        let serials = store.updated_serials("device-1", Some(tag)).unwrap();
+        assert_eq!(serials, vec![(SERIAL.to_string(), tag)]);
+        assert!(
+            store
+                .updated_serials("device-1", Some(tag + 1))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[actix_web::test]
+    async fn pushes_changed_passes_to_registered_devices() {
+        let (apns_url, mut pushes) = mock_apns();
+        let assets = web::Data::new(Assets::new(material(&apns_url)));
+        let store = store();
+        let app = init_service(
+            App::new()
+                .app_data(store.clone())
+                .app_data(assets.clone())
+                .service(web::scope("/passkit/v1").configure(configure)),
+        )
+        .await;
+
+        let registration = TestRequest::post()
+            .uri(&format!(
+                "/passkit/v1/devices/device-1/registrations/{PASS_TYPE}/{SERIAL}"
+            ))
+            .insert_header(("Authorization", format!("{AUTHORIZATION_SCHEME}{TOKEN}")))
+            .set_json(serde_json::json!({ "pushToken": "push-1" }))
+            .to_request();
+        assert_eq!(call_service(&app, registration).await.status(), 201);
+
+        assert!(store.touch_wallet_pass(SERIAL, now()).unwrap());
+        push_updates(&store, &assets.current(), vec![SERIAL.into()]);
+        let push = tokio::time::timeout(Duration::from_secs(10), pushes.recv())
+            .await
+            .expect("no push request within 10 seconds");
+        assert_eq!(push, Some(("push-1".into(), PASS_TYPE.into())));
+
+        let updated = TestRequest::get()
+            .uri(&format!(
+                "/passkit/v1/devices/device-1/registrations/{PASS_TYPE}?passesUpdatedSince=1"
+            ))
+            .to_request();
+        let updated: serde_json::Value = call_and_read_body_json(&app, updated).await;
+        assert_eq!(updated["serialNumbers"], serde_json::json!([SERIAL]));
+    }
+}
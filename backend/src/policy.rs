@@ -0,0 +1,195 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::{fmt, fs};
+
+use crate::error::Error;
+use crate::i18n::Language;
+use crate::utils::Claims;
+
+/// Group every credential requires when no policy file is configured.
+const DEFAULT_MEMBER_GROUP: &str = "mitglieder";
+
+/// Endpoints that never fall back to the `default` rule.
+const RESTRICTED_ENDPOINTS: &[&str] = &["admin"];
+
+/// Which token claims carry the member's data.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClaimNames {
+    pub name: String,
+    pub username: String,
+    /// Array of group names or a space-separated string.
+    pub groups: String,
+    /// Preferred language as a tag such as `en-GB`.
+    pub locale: String,
+}
+
+impl Default for ClaimNames {
+    fn default() -> Self {
+        Self {
+            name: "given_name".into(),
+            username: "preferred_username".into(),
+            groups: "groups".into(),
+            locale: "locale".into(),
+        }
+    }
+}
+
+/// Group constraints; an empty rule allows every authenticated token.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rule {
+    /// All of these groups are required.
+    pub require: Vec<String>,
+    /// At least one of these groups is required (if non-empty).
+    pub require_any: Vec<String>,
+    /// None of these groups may be present.
+    pub forbid: Vec<String>,
+}
+
+impl Rule {
+    pub fn matches(&self, groups: &[String]) -> bool {
+        self.check(groups, "").is_ok()
+    }
+
+    fn check(&self, groups: &[String], scope: &str) -> Result<(), Denial> {
+        let has = |group: &String| groups.contains(group);
+        if let Some(group) = self.forbid.iter().find(|g| has(g)) {
+            return Err(Denial(format!(
+                "{scope} is not available to group '{group}'"
+            )));
+        }
+        if let Some(group) = self.require.iter().find(|g| !has(g)) {
+            return Err(Denial(format!("{scope} requires group '{group}'")));
+        }
+        if !self.require_any.is_empty() && !self.require_any.iter().any(has) {
+            return Err(Denial(format!(
+                "{scope} requires one of the groups {}",
+                self.require_any.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Authorization policy loaded from `POLICY_PATH`.
+///
+/// A request to an endpoint must satisfy the endpoint's rule (or `default`)
+/// and, when it issues a credential, the rule for that credential type.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub claims: ClaimNames,
+    pub default: Rule,
+    /// Keyed by endpoint: `qr`, `pkpass`, `gpass`, `card` or `admin`.
+    pub endpoints: HashMap<String, Rule>,
+    /// Keyed by credential type: `a`, `wi`, `wa` or `t`.
+    pub credentials: HashMap<String, Rule>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            claims: ClaimNames::default(),
+            default: Rule {
+                require: vec![DEFAULT_MEMBER_GROUP.into()],
+                ..Rule::default()
+            },
+            endpoints: HashMap::new(),
+            credentials: HashMap::new(),
+        }
+    }
+}
+
+/// Why a token was refused; the message is safe to show to the caller.
+#[derive(Debug)]
+pub struct Denial(pub String);
+
+impl fmt::Display for Denial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Denial {}
+
+impl Policy {
+    /// Reads the TOML file at `path` (`POLICY_PATH`), or requires the
+    /// `mitglieder` group everywhere without one. Unless the file configures
+    /// the `admin` endpoint, it requires `admin_group` (`ADMIN_GROUP`).
+    pub fn load(
+        path: Option<&str>,
+        admin_group: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut policy = match path {
+            Some(path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|e| format!("failed to read policy {path}: {e}"))?;
+                toml::from_str(&content).map_err(|e| format!("invalid policy {path}: {e}"))?
+            }
+            None => Policy::default(),
+        };
+        if let Some(admin_group) = admin_group {
+            policy.endpoints.entry("admin".into()).or_insert(Rule {
+                require: vec![admin_group.to_string()],
+                ..Rule::default()
+            });
+        }
+        Ok(policy)
+    }
+
+    /// Maps verified token claims onto [`Claims`] using the configured names.
+    pub fn claims(&self, token: Value) -> Result<Claims, Error> {
+        let text = |name: &str| {
+            token
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| Error::Unprocessable(format!("token missing '{name}' claim")))
+        };
+        let groups = match token.get(&self.claims.groups) {
+            Some(Value::Array(groups)) => groups
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            Some(Value::String(groups)) => groups.split_whitespace().map(str::to_string).collect(),
+            _ => Vec::new(),
+        };
+        Ok(Claims {
+            sub: text("sub")?,
+            given_name: text(&self.claims.name)?,
+            preferred_username: text(&self.claims.username)?,
+            groups,
+            azp: text("azp").ok(),
+            language: text(&self.claims.locale)
+                .ok()
+                .and_then(|locale| Language::from_tag(&locale))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Checks `claims` against the rules for `endpoint` and, if given, the
+    /// credential type that is about to be issued.
+    pub fn authorize(
+        &self,
+        endpoint: &str,
+        credential: Option<&str>,
+        claims: &Claims,
+    ) -> Result<(), Denial> {
+        match self.endpoints.get(endpoint) {
+            Some(rule) => rule.check(&claims.groups, endpoint)?,
+            None if RESTRICTED_ENDPOINTS.contains(&endpoint) => {
+                return Err(Denial(format!("{endpoint} access is not configured")));
+            }
+            None => self.default.check(&claims.groups, endpoint)?,
+        }
+        if let Some(t) = credential
+            && let Some(rule) = self.credentials.get(t)
+        {
+            rule.check(&claims.groups, &format!("credential type '{t}'"))?;
+        }
+        Ok(())
+    }
+}
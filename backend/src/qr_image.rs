@@ -0,0 +1,116 @@
+use qrcode::{Color, EcLevel, QrCode};
+use serde::Deserialize;
+use std::fmt::Write;
+
+use crate::error::Error;
+
+/// Query parameters of `/qr.png` and `/qr.svg`.
+#[derive(Deserialize)]
+pub struct ImageQuery {
+    /// Pixels per module.
+    size: Option<u32>,
+    /// Error correction level: `L`, `M`, `Q` or `H`.
+    ecc: Option<String>,
+    /// Width of the quiet zone in modules.
+    quiet_zone: Option<u32>,
+}
+
+/// A QR code with the quiet zone around it, ready to be drawn.
+pub struct QrImage {
+    modules: Vec<Color>,
+    width: u32,
+    size: u32,
+    quiet_zone: u32,
+}
+
+impl QrImage {
+    /// Encodes `data` with the options from `query`.
+    pub fn new(data: &str, query: &ImageQuery) -> Result<Self, Error> {
+        let size = query.size.unwrap_or(8);
+        if !(1..=32).contains(&size) {
+            return Err(Error::Unprocessable(
+                "size must be between 1 and 32 pixels".into(),
+            ));
+        }
+        let quiet_zone = query.quiet_zone.unwrap_or(4);
+        if quiet_zone > 16 {
+            return Err(Error::Unprocessable(
+                "quiet_zone must be at most 16 modules".into(),
+            ));
+        }
+        let ecc = match query.ecc.as_deref().map(str::to_ascii_uppercase).as_deref() {
+            None | Some("M") => EcLevel::M,
+            Some("L") => EcLevel::L,
+            Some("Q") => EcLevel::Q,
+            Some("H") => EcLevel::H,
+            Some(other) => {
+                return Err(Error::Unprocessable(format!(
+                    "unknown error correction level '{other}', expected L, M, Q or H"
+                )));
+            }
+        };
+        let code = QrCode::with_error_correction_level(data, ecc)
+            .map_err(|e| Error::Internal(format!("failed to encode QR code: {e}")))?;
+        Ok(Self {
+            width: code.width() as u32,
+            modules: code.to_colors(),
+            size,
+            quiet_zone,
+        })
+    }
+
+    /// Modules per side including the quiet zone.
+    fn span(&self) -> u32 {
+        self.width + 2 * self.quiet_zone
+    }
+
+    fn is_dark(&self, x: u32, y: u32) -> bool {
+        let (x, y) = (
+            x.wrapping_sub(self.quiet_zone),
+            y.wrapping_sub(self.quiet_zone),
+        );
+        x < self.width
+            && y < self.width
+            && self.modules[(y * self.width + x) as usize] == Color::Dark
+    }
+
+    /// Greyscale PNG, black on white.
+    pub fn png(&self) -> Result<Vec<u8>, Error> {
+        let pixels = self.span() * self.size;
+        let mut data = Vec::with_capacity((pixels * pixels) as usize);
+        for y in 0..pixels {
+            for x in 0..pixels {
+                let dark = self.is_dark(x / self.size, y / self.size);
+                data.push(if dark { 0 } else { 255 });
+            }
+        }
+
+        let failed = |e: png::EncodingError| Error::Internal(format!("failed to write PNG: {e}"));
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, pixels, pixels);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(failed)?;
+        writer.write_image_data(&data).map_err(failed)?;
+        writer.finish().map_err(failed)?;
+        Ok(png)
+    }
+
+    /// SVG drawing the dark modules as a single path, black on white.
+    pub fn svg(&self) -> String {
+        let span = self.span();
+        let pixels = span * self.size;
+        let mut svg = format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?><svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{pixels}" height="{pixels}" viewBox="0 0 {span} {span}" shape-rendering="crispEdges"><rect width="{span}" height="{span}" fill="#fff"/><path fill="#000" d=""##
+        );
+        for y in 0..span {
+            for x in 0..span {
+                if self.is_dark(x, y) {
+                    let _ = write!(svg, "M{x} {y}h1v1h-1z");
+                }
+            }
+        }
+        svg.push_str(r#""/></svg>"#);
+        svg
+    }
+}
@@ -0,0 +1,163 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use log::info;
+use member_id_verify::revocation::{RevocationList, RevokedEntry};
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::assets::Assets;
+use crate::error::Error;
+use crate::google_wallet::{self, ACTIVE, INACTIVE};
+use crate::keys::KeyRing;
+use crate::passkit::push_updates;
+use crate::policy::Policy;
+use crate::require_admin;
+use crate::store::{Revocation, Store};
+
+const COSE_SIGN1_CONTENT_TYPE: &str = "application/cose; cose-type=\"cose-sign1\"";
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct RevokeRequest {
+    #[schema(example = "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f")]
+    pub sub: String,
+    #[schema(example = "Lost phone")]
+    pub reason: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/revocations",
+    params(
+        ("Authorization" = String, Header, description = "Bearer token of an admin")
+    ),
+    responses(
+        (status = 200, description = "All revoked members", body = [Revocation]),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks the admin group", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn list_revocations(
+    req: HttpRequest,
+    store: web::Data<Store>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req).await?;
+    Ok(HttpResponse::Ok().json(store.revocations()?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/revocations",
+    request_body = RevokeRequest,
+    params(
+        ("Authorization" = String, Header, description = "Bearer token of an admin")
+    ),
+    responses(
+        (status = 200, description = "Member revoked; all codes issued until now are rejected", body = Revocation),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks the admin group", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn revoke(
+    req: HttpRequest,
+    body: web::Json<RevokeRequest>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let admin = require_admin(&req).await?;
+    let revocation = Revocation {
+        sub: body.sub.clone(),
+        reason: body.reason.clone(),
+        revoked_at: now(),
+        revoked_by: admin.preferred_username,
+    };
+    store.revoke(&revocation)?;
+    if store.touch_wallet_pass(&revocation.sub, revocation.revoked_at)? {
+        push_updates(&store, &assets.current(), vec![revocation.sub.clone()]);
+    }
+    google_wallet::set_member_state(&store, &policy, &assets, &revocation.sub, INACTIVE)?;
+    info!(
+        "Revoked {} by {}: {}",
+        revocation.sub,
+        revocation.revoked_by,
+        revocation.reason.as_deref().unwrap_or("-")
+    );
+    Ok(HttpResponse::Ok().json(revocation))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/revocations/{sub}",
+    params(
+        ("sub" = String, Path, description = "Subject to reinstate"),
+        ("Authorization" = String, Header, description = "Bearer token of an admin")
+    ),
+    responses(
+        (status = 204, description = "Revocation removed"),
+        (status = 401, description = "Missing or invalid token", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Token lacks the admin group", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Subject was not revoked", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn unrevoke(
+    req: HttpRequest,
+    sub: web::Path<String>,
+    store: web::Data<Store>,
+    policy: web::Data<Policy>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    let admin = require_admin(&req).await?;
+    if !store.unrevoke(&sub)? {
+        return Err(Error::NotFound(format!("{sub} is not revoked")));
+    }
+    if store.touch_wallet_pass(&sub, now())? {
+        push_updates(&store, &assets.current(), vec![sub.clone()]);
+    }
+    google_wallet::set_member_state(&store, &policy, &assets, &sub, ACTIVE)?;
+    info!("Unrevoked {sub} by {}", admin.preferred_username);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    get,
+    path = "/revocations",
+    responses(
+        (status = 200, description = "Timestamped revocation list as COSE_Sign1 signed with the active QR key", content_type = "application/cose"),
+        (status = 500, description = "Internal server error", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn signed_revocation_list(
+    store: web::Data<Store>,
+    assets: web::Data<Assets>,
+) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok()
+        .content_type(COSE_SIGN1_CONTENT_TYPE)
+        .append_header(("Cache-Control", "no-cache"))
+        .body(build_signed_list(&store, &assets.current().qr.key_ring)?))
+}
+
+fn build_signed_list(store: &Store, key_ring: &KeyRing) -> Result<Vec<u8>, Error> {
+    let iat = now();
+    let list = RevocationList {
+        iat,
+        entries: store
+            .revocations()?
+            .into_iter()
+            .map(|r| RevokedEntry {
+                sub: r.sub,
+                revoked_at: r.revoked_at,
+            })
+            .collect(),
+        jtis: store.revoked_jtis(iat)?,
+    };
+    Ok(list.sign(Some(&key_ring.active().kid), key_ring.signing_key())?)
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
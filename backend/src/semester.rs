@@ -0,0 +1,450 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Europe::Berlin;
+use serde::Deserialize;
+use std::fs;
+
+use crate::error::Error;
+use crate::i18n::{Language, Localized};
+
+/// A semester; passes issued during it are valid until `valid_until`.
+#[derive(Clone)]
+pub struct Semester {
+    /// Short name shown on passes, e.g. `SS25`.
+    pub name: String,
+    /// E.g. `Sommersemester 2025`.
+    pub long_name: Localized,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// `end` plus the grace period.
+    pub valid_until: DateTime<Utc>,
+}
+
+impl Semester {
+    /// Days passes stay valid after the end of the semester.
+    pub fn grace_days(&self) -> i64 {
+        (self.valid_until - self.end).num_days()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CalendarFile {
+    semesters: Vec<Period>,
+}
+
+/// A semester as written in the calendar file. `start` and `end` are local
+/// times in Europe/Berlin, either `YYYY-MM-DD` (start or end of that day) or
+/// `YYYY-MM-DDTHH:MM:SS`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Period {
+    name: String,
+    long_name: String,
+    /// English long name; defaults to `long_name`.
+    long_name_en: Option<String>,
+    start: String,
+    end: String,
+}
+
+/// Semester calendar from `SEMESTER_CALENDAR_PATH`. Without a file, summer
+/// semesters run from 15 March to 30 September and winter semesters from
+/// 1 October to 14 March.
+pub struct Calendar {
+    semesters: Vec<Semester>,
+    grace: Duration,
+    renewal: Duration,
+}
+
+impl Calendar {
+    /// `grace_days` (`SEMESTER_GRACE_DAYS`) extends the validity of passes
+    /// past the end of their semester; within the last `renewal_weeks`
+    /// (`SEMESTER_RENEWAL_WEEKS`) of a semester, passes are issued for the
+    /// next one. A calendar file must list contiguous semesters covering now.
+    pub fn load(path: Option<&str>, grace_days: u64, renewal_weeks: u64) -> Result<Self, String> {
+        let grace = Duration::days(grace_days as i64);
+        let renewal = Duration::weeks(renewal_weeks as i64);
+        let Some(path) = path else {
+            return Ok(Self {
+                semesters: Vec::new(),
+                grace,
+                renewal,
+            });
+        };
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("failed to read calendar {path}: {e}"))?;
+        let calendar = Self::parse(&content, grace, renewal)
+            .map_err(|e| format!("invalid calendar {path}: {e}"))?;
+        calendar
+            .check_covers(Utc::now())
+            .map_err(|e| format!("calendar {path}: {e}"))?;
+        Ok(calendar)
+    }
+
+    fn parse(content: &str, grace: Duration, renewal: Duration) -> Result<Self, String> {
+        let file: CalendarFile = toml::from_str(content).map_err(|e| e.to_string())?;
+
+        let mut semesters = Vec::new();
+        for period in file.semesters {
+            let start = local_time(&period.start, NaiveTime::MIN)
+                .map_err(|e| format!("semester {}: invalid start: {e}", period.name))?;
+            let end = local_time(&period.end, end_of_day())
+                .map_err(|e| format!("semester {}: invalid end: {e}", period.name))?;
+            if start >= end {
+                return Err(format!("semester {} ends before it starts", period.name));
+            }
+            semesters.push(Semester {
+                name: period.name,
+                long_name: Localized::new(|language| {
+                    match language {
+                        Language::En => period.long_name_en.as_ref(),
+                        Language::De => None,
+                    }
+                    .unwrap_or(&period.long_name)
+                    .clone()
+                }),
+                start,
+                end,
+                valid_until: end + grace,
+            });
+        }
+        semesters.sort_by_key(|s| s.start);
+        for pair in semesters.windows(2) {
+            if pair[0].end >= pair[1].start {
+                return Err(format!(
+                    "semesters {} and {} overlap",
+                    pair[0].name, pair[1].name
+                ));
+            }
+            // Day-based periods end at 23:59:59 and the next starts a second later.
+            if pair[1].start - pair[0].end > Duration::seconds(1) {
+                return Err(format!(
+                    "gap between semesters {} and {}",
+                    pair[0].name, pair[1].name
+                ));
+            }
+        }
+        if semesters.is_empty() {
+            return Err("no semesters listed".into());
+        }
+        Ok(Self {
+            semesters,
+            grace,
+            renewal,
+        })
+    }
+
+    /// Fails unless a listed semester, or its grace period, runs at `now`.
+    fn check_covers(&self, now: DateTime<Utc>) -> Result<(), String> {
+        if let (Some(first), Some(last)) = (self.semesters.first(), self.semesters.last()) {
+            let date = |t: DateTime<Utc>| t.with_timezone(&Berlin).date_naive();
+            if now < first.start {
+                return Err(format!(
+                    "first semester {} starts on {}, after today",
+                    first.name,
+                    date(first.start)
+                ));
+            }
+            if now > last.valid_until {
+                return Err(format!(
+                    "last semester {} ended on {}; add the next semester",
+                    last.name,
+                    date(last.end)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The semester running at `now`, the one that ended most recently if
+    /// `now` falls into its grace period, or else the next one to start.
+    /// Fails with `503` once the calendar has run out.
+    pub fn current_semester(&self, now: DateTime<Utc>) -> Result<Semester, Error> {
+        if self.semesters.is_empty() {
+            return Ok(self.default_semester(now));
+        }
+        self.semesters
+            .iter()
+            .find(|s| s.start <= now && now <= s.end)
+            .or_else(|| {
+                self.semesters
+                    .iter()
+                    .rev()
+                    .find(|s| s.end < now && now <= s.valid_until)
+            })
+            .or_else(|| self.semesters.iter().find(|s| now < s.start))
+            .cloned()
+            .ok_or_else(|| {
+                Error::Unavailable(
+                    "the semester calendar has run out; add the next semester".into(),
+                )
+            })
+    }
+
+    /// Whether holder data last confirmed by the SSO at `refreshed_at` may
+    /// still back a pass issued at `now`: it must date from the semester
+    /// before the pass semester or later, so holders who stop signing in
+    /// lapse after one automatic renewal.
+    pub fn is_fresh(&self, refreshed_at: DateTime<Utc>, now: DateTime<Utc>) -> Result<bool, Error> {
+        let semester = self.pass_semester(now)?;
+        Ok(refreshed_at >= self.previous_semester(&semester).start)
+    }
+
+    /// The semester before `semester`, derived from the default rule if the
+    /// calendar starts with `semester`.
+    fn previous_semester(&self, semester: &Semester) -> Semester {
+        self.semesters
+            .iter()
+            .rev()
+            .find(|s| s.start < semester.start)
+            .cloned()
+            .unwrap_or_else(|| self.default_semester(semester.start - Duration::seconds(1)))
+    }
+
+    /// The semester a pass issued at `now` is valid for: the next one from
+    /// the start of the renewal window or once the current one has ended,
+    /// otherwise the current one.
+    pub fn pass_semester(&self, now: DateTime<Utc>) -> Result<Semester, Error> {
+        let current = self.current_semester(now)?;
+        if now < current.end - self.renewal {
+            return Ok(current);
+        }
+        Ok(self.next_semester(&current).unwrap_or(current))
+    }
+
+    fn next_semester(&self, semester: &Semester) -> Option<Semester> {
+        if self.semesters.is_empty() {
+            return Some(self.default_semester(semester.end + Duration::seconds(1)));
+        }
+        self.semesters
+            .iter()
+            .find(|s| s.start > semester.start)
+            .cloned()
+    }
+
+    fn default_semester(&self, now: DateTime<Utc>) -> Semester {
+        let today = now.with_timezone(&Berlin).date_naive();
+        let year = today.year();
+        let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap_or_default();
+        let summer_start = date(year, 3, 15);
+        let winter_start = date(year, 10, 1);
+
+        let (name, long_name, start, end) = if today < summer_start {
+            (
+                format!("WS{:02}", (year - 1) % 100),
+                winter_semester(year - 1),
+                date(year - 1, 10, 1),
+                date(year, 3, 14),
+            )
+        } else if today < winter_start {
+            (
+                format!("SS{:02}", year % 100),
+                Localized::new(|language| match language {
+                    Language::De => format!("Sommersemester {year}"),
+                    Language::En => format!("Summer semester {year}"),
+                }),
+                summer_start,
+                date(year, 9, 30),
+            )
+        } else {
+            (
+                format!("WS{:02}", year % 100),
+                winter_semester(year),
+                winter_start,
+                date(year + 1, 3, 14),
+            )
+        };
+        let start = berlin(start.and_time(NaiveTime::MIN)).unwrap_or(now);
+        let end = berlin(end.and_time(end_of_day())).unwrap_or(now);
+        Semester {
+            name,
+            long_name,
+            start,
+            end,
+            valid_until: end + self.grace,
+        }
+    }
+}
+
+fn winter_semester(year: i32) -> Localized {
+    Localized::new(|language| match language {
+        Language::De => format!("Wintersemester {}/{}", year, year + 1),
+        Language::En => format!("Winter semester {}/{}", year, year + 1),
+    })
+}
+
+fn end_of_day() -> NaiveTime {
+    NaiveTime::from_hms_opt(23, 59, 59).unwrap_or(NaiveTime::MIN)
+}
+
+fn local_time(value: &str, time_of_day: NaiveTime) -> Result<DateTime<Utc>, String> {
+    let local = match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => date.and_time(time_of_day),
+        Err(_) => NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+            .map_err(|e| format!("'{value}': {e}"))?,
+    };
+    berlin(local).ok_or_else(|| format!("'{value}' does not exist in Europe/Berlin"))
+}
+
+/// Interprets `local` as Europe/Berlin time; ambiguous times during the
+/// switch from summer time resolve to the earlier instant.
+fn berlin(local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    Berlin
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = r#"
+        [[semesters]]
+        name = "SS25"
+        long_name = "Sommersemester 2025"
+        start = "2025-03-15"
+        end = "2025-09-30"
+
+        [[semesters]]
+        name = "WS25"
+        long_name = "Wintersemester 2025/2026"
+        start = "2025-10-01"
+        end = "2026-03-14"
+    "#;
+
+    fn calendar(grace_days: i64, renewal_weeks: i64) -> Calendar {
+        Calendar::parse(
+            CALENDAR,
+            Duration::days(grace_days),
+            Duration::weeks(renewal_weeks),
+        )
+        .unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn current(calendar: &Calendar, now: &str) -> String {
+        calendar.current_semester(utc(now)).unwrap().name
+    }
+
+    fn pass(calendar: &Calendar, now: &str) -> String {
+        calendar.pass_semester(utc(now)).unwrap().name
+    }
+
+    #[test]
+    fn semesters_switch_at_local_midnight() {
+        let calendar = calendar(0, 0);
+        // 15 March is still winter time (UTC+1), 1 October summer time (UTC+2).
+        assert_eq!(current(&calendar, "2025-03-14T23:00:00Z"), "SS25");
+        assert_eq!(current(&calendar, "2025-09-30T21:59:59Z"), "SS25");
+        assert_eq!(current(&calendar, "2025-09-30T22:00:00Z"), "WS25");
+        assert_eq!(current(&calendar, "2026-03-14T22:59:59Z"), "WS25");
+    }
+
+    #[test]
+    fn rejects_gaps_and_overlaps() {
+        let gap = CALENDAR.replace("2025-10-01", "2025-10-02");
+        let error = Calendar::parse(&gap, Duration::zero(), Duration::zero()).err();
+        assert_eq!(
+            error.as_deref(),
+            Some("gap between semesters SS25 and WS25")
+        );
+
+        let overlap = CALENDAR.replace("2025-10-01", "2025-09-30");
+        let error = Calendar::parse(&overlap, Duration::zero(), Duration::zero()).err();
+        assert_eq!(error.as_deref(), Some("semesters SS25 and WS25 overlap"));
+    }
+
+    #[test]
+    fn must_cover_now() {
+        let calendar = calendar(14, 0);
+        assert!(calendar.check_covers(utc("2025-06-01T00:00:00Z")).is_ok());
+        assert!(calendar.check_covers(utc("2026-03-28T00:00:00Z")).is_ok());
+        assert!(calendar.check_covers(utc("2026-03-29T00:00:00Z")).is_err());
+        assert!(calendar.check_covers(utc("2025-03-01T00:00:00Z")).is_err());
+    }
+
+    #[test]
+    fn grace_period_extends_the_last_semester() {
+        let calendar = calendar(14, 0);
+        let semester = calendar
+            .current_semester(utc("2026-03-20T12:00:00Z"))
+            .unwrap();
+        assert_eq!(semester.name, "WS25");
+        assert_eq!(semester.grace_days(), 14);
+        assert_eq!(semester.valid_until, utc("2026-03-28T22:59:59Z"));
+        assert!(matches!(
+            calendar.current_semester(utc("2026-03-28T23:00:00Z")),
+            Err(Error::Unavailable(_))
+        ));
+    }
+
+    #[test]
+    fn falls_back_to_the_next_semester_in_a_gap() {
+        let mut calendar = calendar(0, 0);
+        calendar.semesters[1].start = utc("2025-10-05T22:00:00Z");
+        assert_eq!(current(&calendar, "2025-10-03T12:00:00Z"), "WS25");
+    }
+
+    #[test]
+    fn renewal_window_issues_for_the_next_semester() {
+        let calendar = calendar(0, 4);
+        assert_eq!(pass(&calendar, "2025-09-02T21:59:58Z"), "SS25");
+        assert_eq!(pass(&calendar, "2025-09-02T21:59:59Z"), "WS25");
+        // Without a next semester, the last one is kept.
+        assert_eq!(pass(&calendar, "2026-03-01T00:00:00Z"), "WS25");
+    }
+
+    #[test]
+    fn holders_lapse_after_one_renewal() {
+        let calendar = calendar(0, 0);
+        let now = utc("2026-01-15T12:00:00Z");
+        // WS25 is running, so a sign-in during SS25 is still fresh.
+        assert!(calendar.is_fresh(utc("2025-03-14T23:00:00Z"), now).unwrap());
+        assert!(!calendar.is_fresh(utc("2025-03-14T22:59:59Z"), now).unwrap());
+        // Before SS25, the previous semester follows the default rule.
+        let now = utc("2025-06-01T12:00:00Z");
+        assert!(calendar.is_fresh(utc("2024-09-30T22:00:00Z"), now).unwrap());
+        assert!(!calendar.is_fresh(utc("2024-09-30T21:59:59Z"), now).unwrap());
+    }
+
+    #[test]
+    fn local_times_around_daylight_saving_changes() {
+        // Clocks jump from 02:00 to 03:00 on 30 March 2025.
+        assert!(local_time("2025-03-30T02:30:00", NaiveTime::MIN).is_err());
+        assert_eq!(
+            local_time("2025-03-30T03:00:00", NaiveTime::MIN).unwrap(),
+            utc("2025-03-30T01:00:00Z")
+        );
+        // 02:30 happens twice on 26 October 2025; the earlier one counts.
+        assert_eq!(
+            local_time("2025-10-26T02:30:00", NaiveTime::MIN).unwrap(),
+            utc("2025-10-26T00:30:00Z")
+        );
+        assert_eq!(
+            local_time("2025-10-26", end_of_day()).unwrap(),
+            utc("2025-10-26T22:59:59Z")
+        );
+    }
+
+    #[test]
+    fn default_semesters_follow_local_time() {
+        let calendar = Calendar {
+            semesters: Vec::new(),
+            ..calendar(0, 0)
+        };
+        let summer = calendar
+            .current_semester(utc("2025-09-30T21:59:59Z"))
+            .unwrap();
+        assert_eq!(summer.name, "SS25");
+        assert_eq!(summer.start, utc("2025-03-14T23:00:00Z"));
+        assert_eq!(summer.end, utc("2025-09-30T21:59:59Z"));
+        let winter = calendar
+            .current_semester(utc("2025-09-30T22:00:00Z"))
+            .unwrap();
+        assert_eq!(winter.name, "WS25");
+        assert_eq!(winter.end, utc("2026-03-14T22:59:59Z"));
+    }
+}
@@ -0,0 +1,640 @@
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::i18n::Language;
+
+const MIGRATIONS: &str = "
+CREATE TABLE IF NOT EXISTS revocations (
+    sub        TEXT PRIMARY KEY,
+    reason     TEXT,
+    revoked_at INTEGER NOT NULL,
+    revoked_by TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS issuances (
+    jti           TEXT PRIMARY KEY,
+    sub           TEXT NOT NULL,
+    type          TEXT NOT NULL,
+    iat           INTEGER NOT NULL,
+    exp           INTEGER NOT NULL,
+    client        TEXT,
+    revoked_at    INTEGER,
+    revoked_by    TEXT,
+    revoke_reason TEXT
+);
+CREATE INDEX IF NOT EXISTS issuances_sub ON issuances (sub, iat);
+
+CREATE TABLE IF NOT EXISTS wallet_passes (
+    serial_number        TEXT PRIMARY KEY,
+    authentication_token TEXT NOT NULL,
+    given_name           TEXT NOT NULL,
+    preferred_username   TEXT NOT NULL,
+    groups               TEXT NOT NULL,
+    client               TEXT,
+    semester             TEXT NOT NULL,
+    refreshed_at         INTEGER NOT NULL,
+    updated_at           INTEGER NOT NULL,
+    language             TEXT
+);
+
+CREATE TABLE IF NOT EXISTS google_objects (
+    sub                TEXT PRIMARY KEY,
+    object_id          TEXT NOT NULL,
+    given_name         TEXT NOT NULL,
+    preferred_username TEXT NOT NULL,
+    groups             TEXT NOT NULL,
+    client             TEXT,
+    semester           TEXT NOT NULL,
+    state              TEXT NOT NULL,
+    jti                TEXT,
+    refreshed_at       INTEGER NOT NULL,
+    updated_at         INTEGER NOT NULL,
+    language           TEXT
+);
+CREATE INDEX IF NOT EXISTS google_objects_jti ON google_objects (jti);
+
+CREATE TABLE IF NOT EXISTS device_registrations (
+    device_library_identifier TEXT NOT NULL,
+    serial_number             TEXT NOT NULL,
+    push_token                TEXT NOT NULL,
+    registered_at             INTEGER NOT NULL,
+    pushed_at                 INTEGER,
+    push_status               TEXT,
+    PRIMARY KEY (device_library_identifier, serial_number)
+);
+CREATE INDEX IF NOT EXISTS device_registrations_serial
+    ON device_registrations (serial_number);
+";
+
+/// Columns added to existing tables, as `(table, column, type)`.
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("wallet_passes", "language", "TEXT"),
+    ("google_objects", "language", "TEXT"),
+];
+
+const ISSUANCE_COLUMNS: &str =
+    "jti, sub, type, iat, exp, client, revoked_at, revoked_by, revoke_reason";
+
+const WALLET_PASS_COLUMNS: &str = "serial_number, authentication_token, given_name, \
+    preferred_username, groups, client, semester, refreshed_at, updated_at, language";
+
+/// Local SQLite storage shared by all workers.
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Revocation {
+    #[schema(example = "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f")]
+    pub sub: String,
+    #[schema(example = "Lost phone")]
+    pub reason: Option<String>,
+    /// Codes for `sub` issued at or before this timestamp are rejected.
+    #[schema(example = "1719322624")]
+    pub revoked_at: u64,
+    #[schema(example = "admin")]
+    pub revoked_by: String,
+}
+
+/// One QR code handed out by `generate_qr`.
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Issuance {
+    #[schema(example = "5f0c2e9ab81d4a37")]
+    pub jti: String,
+    #[schema(example = "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f")]
+    pub sub: String,
+    /// Credential type (`a`, `wi`, `wa` or `t`).
+    #[serde(rename = "type")]
+    #[schema(example = "wi")]
+    pub t: String,
+    #[schema(example = "1719322624")]
+    pub iat: u64,
+    #[schema(example = "1727733599")]
+    pub exp: u64,
+    /// OAuth client (`azp`) of the token that requested the code.
+    #[schema(example = "neuland-next")]
+    pub client: Option<String>,
+    pub revoked_at: Option<u64>,
+    pub revoked_by: Option<String>,
+    pub revoke_reason: Option<String>,
+}
+
+/// An Apple Wallet pass that devices may register for updates. The serial
+/// number is the member's `sub`; the holder data is what the pass was last
+/// built from, so it can be rebuilt without an SSO token.
+pub struct WalletPass {
+    pub serial_number: String,
+    pub authentication_token: String,
+    pub given_name: String,
+    pub preferred_username: String,
+    pub groups: Vec<String>,
+    pub client: Option<String>,
+    /// Semester the pass content was built for.
+    pub semester: String,
+    /// Last time the holder data came from a verified SSO token.
+    pub refreshed_at: u64,
+    /// Last time the pass content changed; the PassKit update tag.
+    pub updated_at: u64,
+    pub language: Language,
+}
+
+const WALLET_OBJECT_COLUMNS: &str = "sub, object_id, given_name, preferred_username, \
+    groups, client, semester, state, jti, refreshed_at, updated_at, language";
+
+/// A member's Google Wallet object as last sent to the Wallet API, with the
+/// holder data needed to rebuild it.
+pub struct WalletObject {
+    pub sub: String,
+    pub object_id: String,
+    pub given_name: String,
+    pub preferred_username: String,
+    pub groups: Vec<String>,
+    pub client: Option<String>,
+    pub semester: String,
+    /// `ACTIVE`, `INACTIVE` (member revoked) or `EXPIRED` (code revoked).
+    pub state: String,
+    /// `jti` of the code on the object.
+    pub jti: Option<String>,
+    /// Last time the holder data came from a verified SSO token.
+    pub refreshed_at: u64,
+    pub updated_at: u64,
+    pub language: Language,
+}
+
+/// A device registered for updates of a [`WalletPass`].
+pub struct Registration {
+    pub device_library_identifier: String,
+    pub serial_number: String,
+    pub push_token: String,
+}
+
+impl Store {
+    /// Opens the database at `path` and applies the schema.
+    pub fn open(path: &str) -> Result<Self, rusqlite::Error> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(MIGRATIONS)?;
+        for (table, column, column_type) in ADDED_COLUMNS {
+            let exists: bool = conn.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+                params![table, column],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE {table} ADD COLUMN {column} {column_type}"
+                ))?;
+            }
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn revoke(&self, revocation: &Revocation) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            "INSERT INTO revocations (sub, reason, revoked_at, revoked_by)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (sub) DO UPDATE SET
+                reason = excluded.reason,
+                revoked_at = excluded.revoked_at,
+                revoked_by = excluded.revoked_by",
+            params![
+                revocation.sub,
+                revocation.reason,
+                revocation.revoked_at,
+                revocation.revoked_by
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Removes the revocation for `sub`; returns whether one existed.
+    pub fn unrevoke(&self, sub: &str) -> Result<bool, rusqlite::Error> {
+        let removed = self
+            .conn()
+            .execute("DELETE FROM revocations WHERE sub = ?1", params![sub])?;
+        Ok(removed > 0)
+    }
+
+    pub fn revocation(&self, sub: &str) -> Result<Option<Revocation>, rusqlite::Error> {
+        self.conn()
+            .query_row(
+                "SELECT sub, reason, revoked_at, revoked_by FROM revocations WHERE sub = ?1",
+                params![sub],
+                revocation_from_row,
+            )
+            .optional()
+    }
+
+    pub fn revocations(&self) -> Result<Vec<Revocation>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT sub, reason, revoked_at, revoked_by FROM revocations ORDER BY revoked_at",
+        )?;
+        stmt.query_map([], revocation_from_row)?.collect()
+    }
+
+    pub fn record_issuance(&self, issuance: &Issuance) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            "INSERT INTO issuances (jti, sub, type, iat, exp, client) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                issuance.jti,
+                issuance.sub,
+                issuance.t,
+                issuance.iat,
+                issuance.exp,
+                issuance.client
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn issuance(&self, jti: &str) -> Result<Option<Issuance>, rusqlite::Error> {
+        self.conn()
+            .query_row(
+                &format!("SELECT {ISSUANCE_COLUMNS} FROM issuances WHERE jti = ?1"),
+                params![jti],
+                issuance_from_row,
+            )
+            .optional()
+    }
+
+    /// Issuances, newest first, optionally restricted to one subject.
+    pub fn issuances(
+        &self,
+        sub: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<Issuance>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ISSUANCE_COLUMNS} FROM issuances
+             WHERE ?1 IS NULL OR sub = ?1
+             ORDER BY iat DESC LIMIT ?2"
+        ))?;
+        stmt.query_map(params![sub, limit], issuance_from_row)?
+            .collect()
+    }
+
+    /// The most recent issuance of type `t` for `sub`.
+    pub fn latest_issuance(&self, sub: &str, t: &str) -> Result<Option<Issuance>, rusqlite::Error> {
+        self.conn()
+            .query_row(
+                &format!(
+                    "SELECT {ISSUANCE_COLUMNS} FROM issuances WHERE sub = ?1 AND type = ?2
+                     ORDER BY iat DESC, rowid DESC LIMIT 1"
+                ),
+                params![sub, t],
+                issuance_from_row,
+            )
+            .optional()
+    }
+
+    /// Marks a single issuance as revoked; returns whether it exists.
+    pub fn revoke_issuance(
+        &self,
+        jti: &str,
+        revoked_at: u64,
+        revoked_by: &str,
+        reason: Option<&str>,
+    ) -> Result<bool, rusqlite::Error> {
+        let updated = self.conn().execute(
+            "UPDATE issuances SET revoked_at = ?2, revoked_by = ?3, revoke_reason = ?4
+             WHERE jti = ?1",
+            params![jti, revoked_at, revoked_by, reason],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Clears the revocation of a single issuance; returns whether it was revoked.
+    pub fn unrevoke_issuance(&self, jti: &str) -> Result<bool, rusqlite::Error> {
+        let updated = self.conn().execute(
+            "UPDATE issuances SET revoked_at = NULL, revoked_by = NULL, revoke_reason = NULL
+             WHERE jti = ?1 AND revoked_at IS NOT NULL",
+            params![jti],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// IDs of revoked issuances that would otherwise still be valid at `now`.
+    pub fn revoked_jtis(&self, now: u64) -> Result<Vec<String>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT jti FROM issuances WHERE revoked_at IS NOT NULL AND exp >= ?1 ORDER BY jti",
+        )?;
+        stmt.query_map(params![now], |row| row.get(0))?.collect()
+    }
+
+    pub fn wallet_pass(&self, serial_number: &str) -> Result<Option<WalletPass>, rusqlite::Error> {
+        self.conn()
+            .query_row(
+                &format!(
+                    "SELECT {WALLET_PASS_COLUMNS} FROM wallet_passes WHERE serial_number = ?1"
+                ),
+                params![serial_number],
+                wallet_pass_from_row,
+            )
+            .optional()
+    }
+
+    pub fn save_wallet_pass(&self, pass: &WalletPass) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            &format!(
+                "INSERT OR REPLACE INTO wallet_passes ({WALLET_PASS_COLUMNS})
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"
+            ),
+            params![
+                pass.serial_number,
+                pass.authentication_token,
+                pass.given_name,
+                pass.preferred_username,
+                serde_json::to_string(&pass.groups).unwrap_or_default(),
+                pass.client,
+                pass.semester,
+                pass.refreshed_at,
+                pass.updated_at,
+                pass.language.code()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Marks the pass as changed so registered devices fetch it again;
+    /// returns whether it exists.
+    pub fn touch_wallet_pass(
+        &self,
+        serial_number: &str,
+        now: u64,
+    ) -> Result<bool, rusqlite::Error> {
+        let updated = self.conn().execute(
+            "UPDATE wallet_passes SET updated_at = ?2 WHERE serial_number = ?1",
+            params![serial_number, now],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Marks every pass built for another semester as changed; returns
+    /// their serial numbers.
+    pub fn roll_over_semester(
+        &self,
+        semester: &str,
+        now: u64,
+    ) -> Result<Vec<String>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "UPDATE wallet_passes SET semester = ?1, updated_at = ?2 WHERE semester != ?1
+             RETURNING serial_number",
+        )?;
+        stmt.query_map(params![semester, now], |row| row.get(0))?
+            .collect()
+    }
+
+    /// Registers a device for updates of a pass; returns whether the
+    /// registration is new.
+    pub fn register_device(
+        &self,
+        device_library_identifier: &str,
+        serial_number: &str,
+        push_token: &str,
+        now: u64,
+    ) -> Result<bool, rusqlite::Error> {
+        let conn = self.conn();
+        let exists = conn
+            .query_row(
+                "SELECT 1 FROM device_registrations
+                 WHERE device_library_identifier = ?1 AND serial_number = ?2",
+                params![device_library_identifier, serial_number],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        conn.execute(
+            "INSERT INTO device_registrations
+                (device_library_identifier, serial_number, push_token, registered_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (device_library_identifier, serial_number) DO UPDATE SET
+                push_token = excluded.push_token",
+            params![device_library_identifier, serial_number, push_token, now],
+        )?;
+        Ok(!exists)
+    }
+
+    /// Removes a registration; returns whether it existed.
+    pub fn unregister_device(
+        &self,
+        device_library_identifier: &str,
+        serial_number: &str,
+    ) -> Result<bool, rusqlite::Error> {
+        let removed = self.conn().execute(
+            "DELETE FROM device_registrations
+             WHERE device_library_identifier = ?1 AND serial_number = ?2",
+            params![device_library_identifier, serial_number],
+        )?;
+        Ok(removed > 0)
+    }
+
+    pub fn wallet_object(&self, sub: &str) -> Result<Option<WalletObject>, rusqlite::Error> {
+        self.conn()
+            .query_row(
+                &format!("SELECT {WALLET_OBJECT_COLUMNS} FROM google_objects WHERE sub = ?1"),
+                params![sub],
+                wallet_object_from_row,
+            )
+            .optional()
+    }
+
+    pub fn wallet_object_by_jti(&self, jti: &str) -> Result<Option<WalletObject>, rusqlite::Error> {
+        self.conn()
+            .query_row(
+                &format!("SELECT {WALLET_OBJECT_COLUMNS} FROM google_objects WHERE jti = ?1"),
+                params![jti],
+                wallet_object_from_row,
+            )
+            .optional()
+    }
+
+    /// Objects last built for another semester.
+    pub fn stale_wallet_objects(
+        &self,
+        semester: &str,
+    ) -> Result<Vec<WalletObject>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {WALLET_OBJECT_COLUMNS} FROM google_objects WHERE semester != ?1"
+        ))?;
+        stmt.query_map(params![semester], wallet_object_from_row)?
+            .collect()
+    }
+
+    pub fn save_wallet_object(&self, object: &WalletObject) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            &format!(
+                "INSERT OR REPLACE INTO google_objects ({WALLET_OBJECT_COLUMNS})
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"
+            ),
+            params![
+                object.sub,
+                object.object_id,
+                object.given_name,
+                object.preferred_username,
+                serde_json::to_string(&object.groups).unwrap_or_default(),
+                object.client,
+                object.semester,
+                object.state,
+                object.jti,
+                object.refreshed_at,
+                object.updated_at,
+                object.language.code()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_wallet_object_jti(&self, sub: &str, jti: &str) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            "UPDATE google_objects SET jti = ?2 WHERE sub = ?1",
+            params![sub, jti],
+        )?;
+        Ok(())
+    }
+
+    /// Devices registered for any of `serial_numbers`.
+    pub fn registrations(
+        &self,
+        serial_numbers: &[String],
+    ) -> Result<Vec<Registration>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT device_library_identifier, serial_number, push_token
+             FROM device_registrations WHERE serial_number = ?1",
+        )?;
+        let mut registrations = Vec::new();
+        for serial_number in serial_numbers {
+            for registration in stmt.query_map(params![serial_number], |row| {
+                Ok(Registration {
+                    device_library_identifier: row.get(0)?,
+                    serial_number: row.get(1)?,
+                    push_token: row.get(2)?,
+                })
+            })? {
+                registrations.push(registration?);
+            }
+        }
+        Ok(registrations)
+    }
+
+    /// Records the outcome of the last push notification to a device.
+    pub fn record_push(
+        &self,
+        device_library_identifier: &str,
+        serial_number: &str,
+        status: &str,
+        now: u64,
+    ) -> Result<(), rusqlite::Error> {
+        self.conn().execute(
+            "UPDATE device_registrations SET pushed_at = ?3, push_status = ?4
+             WHERE device_library_identifier = ?1 AND serial_number = ?2",
+            params![device_library_identifier, serial_number, now, status],
+        )?;
+        Ok(())
+    }
+
+    /// Removes every registration using a token APNs reported as invalid.
+    pub fn prune_push_token(&self, push_token: &str) -> Result<usize, rusqlite::Error> {
+        self.conn().execute(
+            "DELETE FROM device_registrations WHERE push_token = ?1",
+            params![push_token],
+        )
+    }
+
+    /// Serial numbers and update tags of the passes registered on a device
+    /// that changed at or after `since`. Tags have a resolution of one
+    /// second, so passes changed in the second of the previous request are
+    /// listed again rather than missed.
+    pub fn updated_serials(
+        &self,
+        device_library_identifier: &str,
+        since: Option<u64>,
+    ) -> Result<Vec<(String, u64)>, rusqlite::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT p.serial_number, p.updated_at
+             FROM device_registrations r
+             JOIN wallet_passes p ON p.serial_number = r.serial_number
+             WHERE r.device_library_identifier = ?1 AND (?2 IS NULL OR p.updated_at >= ?2)
+             ORDER BY p.serial_number",
+        )?;
+        stmt.query_map(params![device_library_identifier, since], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect()
+    }
+}
+
+fn wallet_pass_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<WalletPass> {
+    let groups: String = row.get(4)?;
+    Ok(WalletPass {
+        serial_number: row.get(0)?,
+        authentication_token: row.get(1)?,
+        given_name: row.get(2)?,
+        preferred_username: row.get(3)?,
+        groups: serde_json::from_str(&groups).unwrap_or_default(),
+        client: row.get(5)?,
+        semester: row.get(6)?,
+        refreshed_at: row.get(7)?,
+        updated_at: row.get(8)?,
+        language: language_from_column(row.get(9)?),
+    })
+}
+
+fn wallet_object_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<WalletObject> {
+    let groups: String = row.get(4)?;
+    Ok(WalletObject {
+        sub: row.get(0)?,
+        object_id: row.get(1)?,
+        given_name: row.get(2)?,
+        preferred_username: row.get(3)?,
+        groups: serde_json::from_str(&groups).unwrap_or_default(),
+        client: row.get(5)?,
+        semester: row.get(6)?,
+        state: row.get(7)?,
+        jti: row.get(8)?,
+        refreshed_at: row.get(9)?,
+        updated_at: row.get(10)?,
+        language: language_from_column(row.get(11)?),
+    })
+}
+
+fn language_from_column(language: Option<String>) -> Language {
+    language
+        .as_deref()
+        .and_then(Language::from_tag)
+        .unwrap_or_default()
+}
+
+fn issuance_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Issuance> {
+    Ok(Issuance {
+        jti: row.get(0)?,
+        sub: row.get(1)?,
+        t: row.get(2)?,
+        iat: row.get(3)?,
+        exp: row.get(4)?,
+        client: row.get(5)?,
+        revoked_at: row.get(6)?,
+        revoked_by: row.get(7)?,
+        revoke_reason: row.get(8)?,
+    })
+}
+
+fn revocation_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Revocation> {
+    Ok(Revocation {
+        sub: row.get(0)?,
+        reason: row.get(1)?,
+        revoked_at: row.get(2)?,
+        revoked_by: row.get(3)?,
+    })
+}
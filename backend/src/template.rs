@@ -0,0 +1,372 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+use crate::i18n::{Language, Localized};
+use crate::policy::Rule;
+
+/// Template used unless `PASS_TEMPLATE_PATH` is set.
+const DEFAULT_TEMPLATE: &str = include_str!("../pass-template.toml");
+
+/// Placeholders available in template texts.
+const PLACEHOLDERS: &[&str] = &[
+    "given_name",
+    "username",
+    "sub",
+    "groups",
+    "front_groups",
+    "groups_label",
+    "semester",
+    "semester_long",
+    "semester_end",
+    "valid_until",
+    "grace_period",
+];
+
+/// Layout and branding of the Apple and Google Wallet passes and the
+/// printable card.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassTemplate {
+    pub description: Text,
+    pub apple: AppleTemplate,
+    pub google: GoogleTemplate,
+    /// Taken from the built-in template if missing.
+    #[serde(default = "default_card")]
+    pub card: CardTemplate,
+    /// Checked in order; the first design whose rule the holder's groups
+    /// satisfy is used.
+    #[serde(default)]
+    pub designs: Vec<Design>,
+    /// Used when no design matches; keeps everything from the template.
+    #[serde(skip)]
+    default_design: Design,
+}
+
+/// Overrides of the template for holders in certain groups.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Design {
+    pub name: String,
+    #[serde(default)]
+    pub rule: Rule,
+    #[serde(default)]
+    pub apple: AppleDesign,
+    #[serde(default)]
+    pub google: GoogleDesign,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppleDesign {
+    pub logo_text: Option<Text>,
+    pub label_color: Option<Rgb>,
+    pub foreground_color: Option<Rgb>,
+    pub background_color: Option<Rgb>,
+    /// Directory below `./resources` whose images, e.g. `strip.png`, replace
+    /// or add to the default ones.
+    pub images: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GoogleDesign {
+    pub background_color: Option<Rgb>,
+    pub hero_image_url: Option<String>,
+    /// Class for holders of this design instead of `GOOGLE_WALLET_CLASS_ID`.
+    pub class_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppleTemplate {
+    pub logo_text: Text,
+    pub label_color: Rgb,
+    pub foreground_color: Rgb,
+    pub background_color: Rgb,
+    #[serde(default)]
+    pub associated_store_identifiers: Vec<i32>,
+    pub app_launch_url: Option<String>,
+    /// Shown on the lock screen near the beacon.
+    pub relevant_text: Option<Text>,
+    #[serde(default)]
+    pub header: Vec<Field>,
+    #[serde(default)]
+    pub primary: Vec<Field>,
+    #[serde(default)]
+    pub secondary: Vec<Field>,
+    #[serde(default)]
+    pub auxiliary: Vec<Field>,
+    #[serde(default)]
+    pub back: Vec<Field>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GoogleTemplate {
+    pub card_title: Text,
+    pub header: Text,
+    pub subheader: Option<Text>,
+    pub background_color: Rgb,
+    pub hero_image_url: Option<String>,
+    pub logo_description: Option<Text>,
+    /// Rows of one to three text module keys shown on the card front.
+    #[serde(default)]
+    pub card_rows: Vec<Vec<String>>,
+    #[serde(default)]
+    pub text_modules: Vec<Field>,
+}
+
+/// Texts of the printable card at `/card.pdf`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CardTemplate {
+    pub title: Text,
+    pub name: Text,
+    pub subtitle: Option<Text>,
+    /// Printed below the name as label and value.
+    #[serde(default)]
+    pub fields: Vec<Field>,
+}
+
+fn default_card() -> CardTemplate {
+    toml::from_str::<PassTemplate>(DEFAULT_TEMPLATE)
+        .expect("built-in pass template is valid")
+        .card
+}
+
+/// A pass field; the key is the Apple field key or the Google text module
+/// ID. Fields whose value renders empty are left out.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Field {
+    pub key: String,
+    pub label: Option<Text>,
+    pub value: Text,
+}
+
+/// A string, or a table with one translation per language code.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Text {
+    Plain(String),
+    Translated(BTreeMap<String, String>),
+}
+
+impl Text {
+    /// The text in `language`, falling back to German.
+    fn get(&self, language: Language) -> &str {
+        match self {
+            Text::Plain(text) => text,
+            Text::Translated(texts) => texts
+                .get(language.code())
+                .or_else(|| texts.get(Language::default().code()))
+                .map(String::as_str)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Fills in the placeholders for every language.
+    pub fn render(&self, value: impl Fn(&str, Language) -> Option<String>) -> Localized {
+        Localized::new(|language| {
+            let text = self.get(language);
+            let mut rendered = String::new();
+            let mut rest = text;
+            while let Some(start) = rest.find('{') {
+                rendered.push_str(&rest[..start]);
+                let after = &rest[start + 1..];
+                match after
+                    .find('}')
+                    .and_then(|end| Some((value(&after[..end], language)?, end)))
+                {
+                    Some((value, end)) => {
+                        rendered.push_str(&value);
+                        rest = &after[end + 1..];
+                    }
+                    None => {
+                        rendered.push('{');
+                        rest = after;
+                    }
+                }
+            }
+            rendered.push_str(rest);
+            rendered
+        })
+    }
+
+    fn check(&self, context: &str) -> Result<(), String> {
+        let texts: Vec<&String> = match self {
+            Text::Plain(text) => vec![text],
+            Text::Translated(texts) => {
+                if let Some(code) = texts
+                    .keys()
+                    .find(|code| Language::from_tag(code).is_none_or(|l| l.code() != *code))
+                {
+                    return Err(format!("{context}: unsupported language '{code}'"));
+                }
+                if !texts.contains_key(Language::default().code()) {
+                    return Err(format!(
+                        "{context}: missing '{}' text",
+                        Language::default().code()
+                    ));
+                }
+                texts.values().collect()
+            }
+        };
+        for text in texts {
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find('{') {
+                let after = &rest[start + 1..];
+                let end = after
+                    .find('}')
+                    .ok_or_else(|| format!("{context}: unclosed placeholder in '{text}'"))?;
+                let name = &after[..end];
+                if !PLACEHOLDERS.contains(&name) {
+                    return Err(format!("{context}: unknown placeholder {{{name}}}"));
+                }
+                rest = &after[end + 1..];
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A colour written as `#rrggbb`.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid colour '{value}', expected #rrggbb");
+        let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+        let bytes = hex::decode(hex).map_err(|_| invalid())?;
+        match bytes[..] {
+            [r, g, b] => Ok(Rgb(r, g, b)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+impl PassTemplate {
+    /// Loads the template at `path`, or the built-in one, and checks its
+    /// texts and card rows.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let (name, content) = match path {
+            Some(path) => (
+                path,
+                fs::read_to_string(path)
+                    .map_err(|e| format!("failed to read pass template {path}: {e}"))?,
+            ),
+            None => ("built-in", DEFAULT_TEMPLATE.to_string()),
+        };
+        let mut template: Self =
+            toml::from_str(&content).map_err(|e| format!("invalid pass template {name}: {e}"))?;
+        template.default_design.name = "default".into();
+        template
+            .check()
+            .map_err(|e| format!("invalid pass template {name}: {e}"))?;
+        Ok(template)
+    }
+
+    /// The configured designs and the default one.
+    pub fn designs(&self) -> impl Iterator<Item = &Design> {
+        self.designs.iter().chain([&self.default_design])
+    }
+
+    /// The design for a holder in `groups`.
+    pub fn design(&self, groups: &[String]) -> &Design {
+        self.designs
+            .iter()
+            .find(|design| design.rule.matches(groups))
+            .unwrap_or(&self.default_design)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        self.description.check("description")?;
+        let apple = &self.apple;
+        apple.logo_text.check("apple.logo_text")?;
+        if let Some(text) = &apple.relevant_text {
+            text.check("apple.relevant_text")?;
+        }
+        for (section, fields) in [
+            ("apple.header", &apple.header),
+            ("apple.primary", &apple.primary),
+            ("apple.secondary", &apple.secondary),
+            ("apple.auxiliary", &apple.auxiliary),
+            ("apple.back", &apple.back),
+            ("google.text_modules", &self.google.text_modules),
+            ("card.fields", &self.card.fields),
+        ] {
+            for field in fields {
+                let context = format!("{section}.{}", field.key);
+                if let Some(label) = &field.label {
+                    label.check(&context)?;
+                }
+                field.value.check(&context)?;
+            }
+        }
+
+        let google = &self.google;
+        google.card_title.check("google.card_title")?;
+        google.header.check("google.header")?;
+        for (context, text) in [
+            ("google.subheader", &google.subheader),
+            ("google.logo_description", &google.logo_description),
+        ] {
+            if let Some(text) = text {
+                text.check(context)?;
+            }
+        }
+        let card = &self.card;
+        card.title.check("card.title")?;
+        card.name.check("card.name")?;
+        if let Some(text) = &card.subtitle {
+            text.check("card.subtitle")?;
+        }
+        for row in &google.card_rows {
+            if !(1..=3).contains(&row.len()) {
+                return Err("google.card_rows: rows hold one to three items".into());
+            }
+            if let Some(key) = row
+                .iter()
+                .find(|key| !google.text_modules.iter().any(|field| field.key == **key))
+            {
+                return Err(format!("google.card_rows: unknown text module '{key}'"));
+            }
+        }
+
+        let mut names = vec![self.default_design.name.as_str()];
+        for design in &self.designs {
+            let context = format!("designs.{}", design.name);
+            if design.name.is_empty() || names.contains(&design.name.as_str()) {
+                return Err(format!(
+                    "{context}: design names must be unique and not empty"
+                ));
+            }
+            names.push(&design.name);
+            if let Some(text) = &design.apple.logo_text {
+                text.check(&format!("{context}.apple.logo_text"))?;
+            }
+            if let Some(images) = &design.apple.images
+                && !images
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(format!(
+                    "{context}.apple.images: '{images}' is not a plain directory name"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
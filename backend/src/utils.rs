@@ -1,23 +1,15 @@
-use chrono::{Datelike, NaiveDate, TimeZone, Utc};
-use flate2::{Compression, write::ZlibEncoder};
-use jsonwebtoken::{Algorithm, DecodingKey, TokenData, Validation, decode, decode_header};
+use crate::config::QrConfig;
+use crate::error::Error;
+use crate::i18n::Language;
+use crate::jwks::JwksCache;
+use crate::keys::KeyRing;
+use crate::policy::Policy;
+use crate::revocation::now;
+use crate::store::{Issuance, Store};
+use jsonwebtoken::{TokenData, Validation, decode, decode_header};
 use log::info;
-use p256::ecdsa::SigningKey;
-use p256::ecdsa::signature::Signer;
+use member_id_verify::{PAYLOAD_VERSION, QrPayload};
 use serde::{Deserialize, Serialize};
-use std::{
-    env,
-    time::{SystemTime, UNIX_EPOCH},
-};
-
-#[derive(Serialize)]
-struct QrPayload {
-    sub: String,
-    name: String,
-    t: String,
-    iat: u64,
-    exp: u64,
-}
 
 #[derive(Serialize, utoipa::ToSchema)]
 pub struct QrResponse {
@@ -27,159 +19,203 @@ pub struct QrResponse {
     pub iat: u64,
     #[schema(example = "1720187424")]
     pub exp: u64,
+    #[schema(example = "5f0c2e9ab81d4a37")]
+    pub jti: String,
 }
 
-#[derive(Deserialize)]
-pub struct Claims {
-    pub sub: String,
-    pub given_name: String,
-    pub preferred_username: String,
-    pub groups: Vec<String>,
-}
-
-#[derive(Deserialize)]
-struct Jwk {
-    kid: String,
-    n: String,
-    e: String,
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct VerifyRequest {
+    #[schema(example = "HELLOWORLD123")]
+    pub qr: String,
 }
 
-#[derive(Deserialize)]
-struct Jwks {
-    keys: Vec<Jwk>,
+#[derive(Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Valid,
+    Expired,
+    NotYetValid,
+    Revoked,
+    UnsupportedVersion,
+    UnknownKey,
+    /// Signed by a known key but for another issuer than `QR_ISSUER`.
+    UnknownIssuer,
+    InvalidSignature,
+    Malformed,
 }
 
-pub fn log_public_key() -> Result<(), Box<dyn std::error::Error>> {
-    let hex = public_key_hex()?;
-    info!("QR public key: {hex}");
-    Ok(())
+#[derive(Serialize, utoipa::ToSchema)]
+pub struct VerifyResponse {
+    pub valid: bool,
+    pub verdict: Verdict,
+    #[schema(example = "QR code has expired")]
+    pub reason: Option<String>,
+    pub payload: Option<QrPayload>,
 }
 
-pub fn public_key_hex() -> Result<String, Box<dyn std::error::Error>> {
-    let key_hex = env::var("QR_PRIVATE_KEY_HEX").map_err(|_| "QR_PRIVATE_KEY_HEX not set")?;
-    let key_bytes = hex::decode(key_hex)?;
-    if key_bytes.len() != 32 {
-        return Err("QR_PRIVATE_KEY_HEX must decode to 32 bytes".into());
-    }
-    let arr: [u8; 32] = key_bytes.try_into().map_err(|_| "invalid key length")?;
-    let signing_key = SigningKey::from_bytes((&arr).into())?;
-    let verifying_key = signing_key.verifying_key();
-    let encoded = verifying_key.to_encoded_point(false);
-    Ok(hex::encode(encoded.as_bytes()))
+/// Member data from a verified SSO token, see [`Policy::claims`].
+pub struct Claims {
+    pub sub: String,
+    pub given_name: String,
+    pub preferred_username: String,
+    pub groups: Vec<String>,
+    /// OAuth client the token was issued to.
+    pub azp: Option<String>,
+    /// Language of the wallet passes.
+    pub language: Language,
 }
 
-pub fn current_semester() -> (String, chrono::DateTime<Utc>, String) {
-    let today = Utc::now().date_naive();
-    let year = today.year();
-
-    let summer_start = NaiveDate::from_ymd_opt(year, 3, 15).unwrap();
-    let summer_end = NaiveDate::from_ymd_opt(year, 9, 30).unwrap();
-
-    if today >= summer_start && today <= summer_end {
-        let end = Utc
-            .with_ymd_and_hms(year, 9, 30, 23, 59, 59)
-            .single()
-            .unwrap();
-        (
-            format!("SS{:02}", year % 100),
-            end,
-            format!("Sommersemester {year}"),
-        )
-    } else if today > summer_end {
-        let end = Utc
-            .with_ymd_and_hms(year + 1, 3, 14, 23, 59, 59)
-            .single()
-            .unwrap();
-        (
-            format!("WS{:02}", year % 100),
-            end,
-            format!("Wintersemester {}/{}", year, year + 1),
-        )
-    } else {
-        let end = Utc
-            .with_ymd_and_hms(year, 3, 14, 23, 59, 59)
-            .single()
-            .unwrap();
-        (
-            format!("WS{:02}", (year - 1) % 100),
-            end,
-            format!("Wintersemester {}/{}", year - 1, year),
-        )
+pub fn log_public_key(key_ring: &KeyRing) {
+    for key in key_ring.valid_keys() {
+        let role = if key.active { "active" } else { "verify-only" };
+        info!(
+            "QR public key {} ({role}): {}",
+            key.kid,
+            key.public_key_hex()
+        );
     }
 }
 
-pub async fn verify_token<C>(token: &str) -> Result<TokenData<C>, Box<dyn std::error::Error>>
+pub async fn verify_token<C>(token: &str, jwks: &JwksCache) -> Result<TokenData<C>, Error>
 where
     C: for<'de> Deserialize<'de>,
 {
-    let jwks_url = env::var("JWKS_URL").map_err(|_| "JWKS_URL not set")?;
-    let expected_audience =
-        env::var("EXPECTED_AUDIENCE").map_err(|_| "EXPECTED_AUDIENCE not set")?;
-
-    let header = decode_header(token)?;
-    let kid = header.kid.ok_or("kid missing")?;
-
-    let jwks: Jwks = reqwest::get(&jwks_url).await?.json().await?;
-    let jwk = jwks
-        .keys
-        .iter()
-        .find(|k| k.kid == kid)
-        .ok_or("kid not found")?;
-
-    let decoding_key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e)?;
+    let header =
+        decode_header(token).map_err(|e| Error::Unauthorized(format!("invalid token: {e}")))?;
+    let kid = header
+        .kid
+        .ok_or_else(|| Error::Unauthorized("token header has no kid".into()))?;
+
+    let (decoding_key, algorithm) = jwks.decoding_key(&kid).await?;
+    if header.alg != algorithm {
+        return Err(Error::Unauthorized(format!(
+            "token algorithm {:?} does not match key {kid} ({algorithm:?})",
+            header.alg
+        )));
+    }
 
-    let mut validation = Validation::new(Algorithm::RS256);
+    let mut validation = Validation::new(algorithm);
     validation.validate_exp = true;
     validation.validate_aud = true;
-    validation.set_audience(&[expected_audience]);
+    validation.set_audience(&[&jwks.sso().expected_audience]);
+    if let Some(issuer) = jwks.issuer().await? {
+        validation.set_issuer(&[issuer]);
+        validation.set_required_spec_claims(&["exp", "aud", "iss"]);
+    }
+    validation.leeway = jwks.sso().leeway;
+
+    decode::<C>(token, &decoding_key, &validation)
+        .map_err(|e| Error::Unauthorized(format!("invalid token: {e}")))
+}
 
-    Ok(decode::<C>(token, &decoding_key, &validation)?)
+/// Verifies an SSO token and extracts the member's claims.
+pub async fn authenticate(token: &str, jwks: &JwksCache, policy: &Policy) -> Result<Claims, Error> {
+    let token_data = verify_token::<serde_json::Value>(token, jwks).await?;
+    policy.claims(token_data.claims)
 }
 
-pub async fn generate_qr(
-    token: &str,
+/// Issues a QR code for the holder of an already verified token.
+pub fn generate_qr(
+    claims: &Claims,
     qr_type: &str,
     max_age: u64,
-) -> Result<QrResponse, Box<dyn std::error::Error>> {
-    let token_data = verify_token::<Claims>(token).await?;
-
-    if !token_data.claims.groups.iter().any(|g| g == "mitglieder") {
-        return Err("token missing required 'mitglieder' group".into());
-    }
-
-    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    store: &Store,
+    config: &QrConfig,
+) -> Result<QrResponse, Error> {
+    let key_ring = &config.key_ring;
+    let now = now();
+    let jti = hex::encode(rand::random::<[u8; 8]>());
     let payload = QrPayload {
-        sub: token_data.claims.sub,
-        name: token_data.claims.given_name,
+        sub: claims.sub.clone(),
+        name: claims.given_name.clone(),
         t: qr_type.to_string(),
         iat: now,
         exp: now + max_age,
+        kid: Some(key_ring.active().kid.clone()),
+        iss: config.issuer.clone(),
+        jti: Some(jti.clone()),
+        v: Some(PAYLOAD_VERSION),
+        r: qr_roles(&claims.groups, &config.roles),
     };
 
-    let cbor = serde_cbor::to_vec(&payload)?;
-
-    let key_hex = env::var("QR_PRIVATE_KEY_HEX").map_err(|_| "QR_PRIVATE_KEY_HEX not set")?;
-    let key_bytes = hex::decode(key_hex)?;
-    if key_bytes.len() != 32 {
-        return Err("QR_PRIVATE_KEY_HEX must decode to 32 bytes".into());
-    }
-    let arr: [u8; 32] = key_bytes.try_into().map_err(|_| "invalid key length")?;
-    let signing_key = SigningKey::from_bytes((&arr).into())?;
-    let signature: p256::ecdsa::Signature = signing_key.sign(&cbor);
+    let qr = member_id_verify::encode_with(&payload, key_ring.signing_key(), config.format)?;
 
-    let mut combined = Vec::new();
-    combined.extend_from_slice(&cbor);
-    combined.extend_from_slice(&signature.to_bytes());
-
-    use std::io::Write;
-    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(&combined)?;
-    let compressed = encoder.finish()?;
+    store.record_issuance(&Issuance {
+        jti: jti.clone(),
+        sub: payload.sub,
+        t: payload.t,
+        iat: payload.iat,
+        exp: payload.exp,
+        client: claims.azp.clone(),
+        revoked_at: None,
+        revoked_by: None,
+        revoke_reason: None,
+    })?;
 
     Ok(QrResponse {
-        qr: base45::encode(compressed),
+        qr,
         iat: payload.iat,
         exp: payload.exp,
+        jti,
+    })
+}
+
+/// Verifies a QR code produced by [`generate_qr`]. Malformed or forged codes
+/// yield a rejected verdict; only server-side problems (e.g. a missing key)
+/// are errors.
+pub fn verify_qr(qr: &str, store: &Store, config: &QrConfig) -> Result<VerifyResponse, Error> {
+    let error = match member_id_verify::decode_and_verify(qr, &config.key_ring) {
+        Ok(payload) => {
+            if let (Some(iss), Some(expected)) = (&payload.iss, &config.issuer)
+                && iss != expected
+            {
+                return Ok(VerifyResponse {
+                    valid: false,
+                    verdict: Verdict::UnknownIssuer,
+                    reason: Some(format!("issued by '{iss}', expected '{expected}'")),
+                    payload: Some(payload),
+                });
+            }
+            let member_revoked = store
+                .revocation(&payload.sub)?
+                .is_some_and(|revocation| payload.iat <= revocation.revoked_at);
+            let issuance_revoked = match &payload.jti {
+                Some(jti) => store
+                    .issuance(jti)?
+                    .is_some_and(|issuance| issuance.revoked_at.is_some()),
+                None => false,
+            };
+            if member_revoked || issuance_revoked {
+                return Ok(VerifyResponse {
+                    valid: false,
+                    verdict: Verdict::Revoked,
+                    reason: Some("credential has been revoked".into()),
+                    payload: Some(payload),
+                });
+            }
+            return Ok(VerifyResponse {
+                valid: true,
+                verdict: Verdict::Valid,
+                reason: None,
+                payload: Some(payload),
+            });
+        }
+        Err(e) => e,
+    };
+
+    let verdict = match error {
+        member_id_verify::Error::Expired(_) => Verdict::Expired,
+        member_id_verify::Error::NotYetValid(_) => Verdict::NotYetValid,
+        member_id_verify::Error::UnsupportedVersion(_) => Verdict::UnsupportedVersion,
+        member_id_verify::Error::UnknownKey(_) => Verdict::UnknownKey,
+        member_id_verify::Error::InvalidSignature => Verdict::InvalidSignature,
+        _ => Verdict::Malformed,
+    };
+    Ok(VerifyResponse {
+        valid: false,
+        verdict,
+        reason: Some(error.to_string()),
+        payload: error.payload().cloned(),
     })
 }
 
@@ -196,6 +232,17 @@ pub fn capitalize_groups(groups: &[String]) -> Vec<String> {
         .collect()
 }
 
+/// Roles embedded in QR codes: the groups shown on passes (see
+/// [`filter_groups`]), narrowed to the `QR_ROLES` allowlist if configured so
+/// the code stays small.
+pub fn qr_roles(groups: &[String], allowlist: &[String]) -> Option<Vec<String>> {
+    let roles: Vec<String> = filter_groups(groups)
+        .into_iter()
+        .filter(|g| allowlist.is_empty() || allowlist.contains(g))
+        .collect();
+    (!roles.is_empty()).then_some(roles)
+}
+
 pub fn filter_groups(groups: &[String]) -> Vec<String> {
     groups
         .iter()
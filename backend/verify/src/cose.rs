@@ -0,0 +1,401 @@
+//! COSE_Sign1 (RFC 9052) container with CWT claims (RFC 8392).
+//!
+//! The protected header carries `alg: ES256` and the `kid`; the payload is a
+//! CWT claims map using the registered keys for `iss`, `sub`, `exp`, `iat`
+//! and `cti` (carrying the `jti`) plus the private text claims `name`, `t`, `v` and `r`.
+
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+
+use crate::{Error, KeyResolver, QrPayload, verify_signature};
+
+/// CBOR tag of a COSE_Sign1 structure.
+pub const COSE_SIGN1_TAG: u64 = 18;
+
+/// First byte of a tagged COSE_Sign1 structure, used to tell it apart from
+/// the legacy CBOR map.
+pub(crate) const COSE_SIGN1_PREFIX: u8 = 0xd2;
+
+const HEADER_ALG: i128 = 1;
+const HEADER_KID: i128 = 4;
+const ALG_ES256: i128 = -7;
+
+const CLAIM_ISS: i128 = 1;
+const CLAIM_SUB: i128 = 2;
+const CLAIM_EXP: i128 = 4;
+const CLAIM_IAT: i128 = 6;
+const CLAIM_CTI: i128 = 7;
+const CLAIM_NAME: &str = "name";
+const CLAIM_TYPE: &str = "t";
+const CLAIM_VERSION: &str = "v";
+const CLAIM_ROLES: &str = "r";
+
+/// A decoded COSE_Sign1 structure that still needs its signature checked.
+pub(crate) struct Sign1 {
+    pub kid: Option<String>,
+    pub payload: Vec<u8>,
+    /// The `Sig_structure` the signature was computed over.
+    pub to_be_signed: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+/// Wraps arbitrary `payload` bytes into a COSE_Sign1 structure signed with
+/// ES256, announcing `kid` in the protected header.
+pub fn sign_bytes(payload: &[u8], kid: Option<&str>, key: &SigningKey) -> Result<Vec<u8>, Error> {
+    let mut header = BTreeMap::new();
+    header.insert(Value::Integer(HEADER_ALG), Value::Integer(ALG_ES256));
+    if let Some(kid) = kid {
+        header.insert(
+            Value::Integer(HEADER_KID),
+            Value::Bytes(kid.as_bytes().to_vec()),
+        );
+    }
+    let protected = serde_cbor::to_vec(&Value::Map(header))?;
+
+    let to_be_signed = sig_structure(&protected, payload)?;
+    let signature: Signature = key.sign(&to_be_signed);
+
+    let sign1 = Value::Tag(
+        COSE_SIGN1_TAG,
+        Box::new(Value::Array(vec![
+            Value::Bytes(protected),
+            Value::Map(BTreeMap::new()),
+            Value::Bytes(payload.to_vec()),
+            Value::Bytes(signature.to_bytes().to_vec()),
+        ])),
+    );
+    Ok(serde_cbor::to_vec(&sign1)?)
+}
+
+/// Checks the signature of a COSE_Sign1 structure against the keys resolved
+/// for its `kid` and returns the signed payload bytes.
+pub fn verify_bytes<K>(data: &[u8], keys: &K) -> Result<Vec<u8>, Error>
+where
+    K: KeyResolver + ?Sized,
+{
+    let sign1 = open(data)?;
+    verify_signature(
+        keys,
+        sign1.kid.as_deref(),
+        &sign1.to_be_signed,
+        &sign1.signature,
+    )?;
+    Ok(sign1.payload)
+}
+
+pub(crate) fn sign(payload: &QrPayload, key: &SigningKey) -> Result<Vec<u8>, Error> {
+    let claims = serde_cbor::to_vec(&claims_to_cbor(payload))?;
+    sign_bytes(&claims, payload.kid.as_deref(), key)
+}
+
+pub(crate) fn open(data: &[u8]) -> Result<Sign1, Error> {
+    let value: Value = serde_cbor::from_slice(data)?;
+    let Value::Tag(COSE_SIGN1_TAG, inner) = value else {
+        return Err(Error::Cose("not a tagged COSE_Sign1 structure"));
+    };
+    let Value::Array(items) = *inner else {
+        return Err(Error::Cose("COSE_Sign1 is not an array"));
+    };
+    let [protected, _unprotected, payload, signature]: [Value; 4] = items
+        .try_into()
+        .map_err(|_| Error::Cose("COSE_Sign1 must have four elements"))?;
+    let (Value::Bytes(protected), Value::Bytes(payload), Value::Bytes(signature)) =
+        (protected, payload, signature)
+    else {
+        return Err(Error::Cose("COSE_Sign1 elements have the wrong type"));
+    };
+
+    let Value::Map(header) = serde_cbor::from_slice(&protected)? else {
+        return Err(Error::Cose("protected header is not a map"));
+    };
+    match header.get(&Value::Integer(HEADER_ALG)) {
+        Some(Value::Integer(ALG_ES256)) => {}
+        Some(Value::Integer(alg)) => return Err(Error::UnsupportedAlgorithm(*alg)),
+        _ => return Err(Error::Cose("missing algorithm in protected header")),
+    }
+    let kid = match header.get(&Value::Integer(HEADER_KID)) {
+        Some(Value::Bytes(kid)) => Some(
+            String::from_utf8(kid.clone()).map_err(|_| Error::Cose("kid is not valid UTF-8"))?,
+        ),
+        Some(_) => return Err(Error::Cose("kid must be a byte string")),
+        None => None,
+    };
+
+    Ok(Sign1 {
+        kid,
+        to_be_signed: sig_structure(&protected, &payload)?,
+        payload,
+        signature,
+    })
+}
+
+/// Decodes the CWT claims of a QR code's COSE_Sign1 structure.
+pub(crate) fn decode_claims(sign1: &Sign1) -> Result<QrPayload, Error> {
+    let mut payload = claims_from_cbor(serde_cbor::from_slice(&sign1.payload)?)?;
+    payload.kid = sign1.kid.clone();
+    Ok(payload)
+}
+
+fn sig_structure(protected: &[u8], payload: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(serde_cbor::to_vec(&Value::Array(vec![
+        Value::Text("Signature1".into()),
+        Value::Bytes(protected.to_vec()),
+        Value::Bytes(Vec::new()),
+        Value::Bytes(payload.to_vec()),
+    ]))?)
+}
+
+fn claims_to_cbor(payload: &QrPayload) -> Value {
+    let mut claims = BTreeMap::new();
+    if let Some(iss) = &payload.iss {
+        claims.insert(Value::Integer(CLAIM_ISS), Value::Text(iss.clone()));
+    }
+    claims.insert(Value::Integer(CLAIM_SUB), Value::Text(payload.sub.clone()));
+    claims.insert(
+        Value::Integer(CLAIM_EXP),
+        Value::Integer(payload.exp.into()),
+    );
+    claims.insert(
+        Value::Integer(CLAIM_IAT),
+        Value::Integer(payload.iat.into()),
+    );
+    if let Some(jti) = &payload.jti {
+        claims.insert(
+            Value::Integer(CLAIM_CTI),
+            Value::Bytes(jti.as_bytes().to_vec()),
+        );
+    }
+    claims.insert(
+        Value::Text(CLAIM_NAME.into()),
+        Value::Text(payload.name.clone()),
+    );
+    claims.insert(
+        Value::Text(CLAIM_TYPE.into()),
+        Value::Text(payload.t.clone()),
+    );
+    if let Some(v) = payload.v {
+        claims.insert(Value::Text(CLAIM_VERSION.into()), Value::Integer(v.into()));
+    }
+    if let Some(roles) = &payload.r {
+        claims.insert(
+            Value::Text(CLAIM_ROLES.into()),
+            Value::Array(roles.iter().cloned().map(Value::Text).collect()),
+        );
+    }
+    Value::Map(claims)
+}
+
+fn claims_from_cbor(value: Value) -> Result<QrPayload, Error> {
+    let Value::Map(mut claims) = value else {
+        return Err(Error::Cose("CWT claims are not a map"));
+    };
+    let iss = take_text(&mut claims, Value::Integer(CLAIM_ISS), "iss")?;
+    let sub = take_text(&mut claims, Value::Integer(CLAIM_SUB), "sub")?
+        .ok_or(Error::MissingClaim("sub"))?;
+    let name = take_text(&mut claims, Value::Text(CLAIM_NAME.into()), "name")?
+        .ok_or(Error::MissingClaim("name"))?;
+    let t = take_text(&mut claims, Value::Text(CLAIM_TYPE.into()), "t")?
+        .ok_or(Error::MissingClaim("t"))?;
+    let exp = take_timestamp(&mut claims, CLAIM_EXP, "exp")?;
+    let iat = take_timestamp(&mut claims, CLAIM_IAT, "iat")?;
+    let jti = match claims.remove(&Value::Integer(CLAIM_CTI)) {
+        Some(Value::Bytes(cti)) => {
+            Some(String::from_utf8(cti).map_err(|_| Error::MissingClaim("cti"))?)
+        }
+        None => None,
+        Some(_) => return Err(Error::MissingClaim("cti")),
+    };
+    let v = match claims.remove(&Value::Text(CLAIM_VERSION.into())) {
+        Some(Value::Integer(v)) => Some(u8::try_from(v).map_err(|_| Error::MissingClaim("v"))?),
+        None => None,
+        Some(_) => return Err(Error::MissingClaim("v")),
+    };
+    let r = match claims.remove(&Value::Text(CLAIM_ROLES.into())) {
+        Some(Value::Array(roles)) => Some(
+            roles
+                .into_iter()
+                .map(|role| match role {
+                    Value::Text(role) => Ok(role),
+                    _ => Err(Error::MissingClaim("r")),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+        Some(_) => return Err(Error::MissingClaim("r")),
+    };
+
+    Ok(QrPayload {
+        sub,
+        name,
+        t,
+        iat,
+        exp,
+        kid: None,
+        iss,
+        jti,
+        v,
+        r,
+    })
+}
+
+fn take_text(
+    claims: &mut BTreeMap<Value, Value>,
+    key: Value,
+    name: &'static str,
+) -> Result<Option<String>, Error> {
+    match claims.remove(&key) {
+        Some(Value::Text(value)) => Ok(Some(value)),
+        None => Ok(None),
+        Some(_) => Err(Error::MissingClaim(name)),
+    }
+}
+
+fn take_timestamp(
+    claims: &mut BTreeMap<Value, Value>,
+    key: i128,
+    name: &'static str,
+) -> Result<u64, Error> {
+    match claims.remove(&Value::Integer(key)) {
+        Some(Value::Integer(value)) => u64::try_from(value).map_err(|_| Error::MissingClaim(name)),
+        _ => Err(Error::MissingClaim(name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::VerifyingKey;
+    use p256::ecdsa::signature::Verifier;
+
+    /// COSE_Sign1 for [`payload`] signed with [`key`]. ES256 signatures use
+    /// RFC 6979 nonces, so the encoding is stable.
+    const VECTOR: &str = concat!(
+        "d2844ba201260446323032352d31a05890a9017668747470733a2f2f69642e65",
+        "78616d706c652e6f726702782433663263396131652d356237642d346331612d",
+        "396538662d306131623263336434653566041a684eef90061a684ee180075824",
+        "37643165346232632d396133662d346535382d623663312d3266306139643865",
+        "3763363561728168766f727374616e646174627769617602646e616d65634d61",
+        "785840e27ebc7184ceca91b2f8457c87cd4a0410e64c0e0d1e502f008ceb5fe9",
+        "7b89d037772715f67a0fe5e866fb2a5d0555d03b43f0d6a3879953f175aeb106",
+        "c7a077",
+    );
+
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn payload() -> QrPayload {
+        QrPayload {
+            sub: "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f".into(),
+            name: "Max".into(),
+            t: "wi".into(),
+            iat: 1_750_000_000,
+            exp: 1_750_003_600,
+            kid: Some("2025-1".into()),
+            iss: Some("https://id.example.org".into()),
+            jti: Some("7d1e4b2c-9a3f-4e58-b6c1-2f0a9d8e7c65".into()),
+            v: Some(2),
+            r: Some(vec!["vorstand".into()]),
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn verifies(sign1: &Sign1, key: &VerifyingKey) -> bool {
+        let signature = Signature::from_slice(&sign1.signature).unwrap();
+        key.verify(&sign1.to_be_signed, &signature).is_ok()
+    }
+
+    #[test]
+    fn matches_fixed_vector() {
+        let encoded = sign(&payload(), &key()).unwrap();
+        assert_eq!(hex(&encoded), VECTOR);
+        assert_eq!(encoded[0], COSE_SIGN1_PREFIX);
+
+        let Value::Tag(COSE_SIGN1_TAG, inner) = serde_cbor::from_slice(&encoded).unwrap() else {
+            panic!("not tagged as COSE_Sign1");
+        };
+        let Value::Array(items) = *inner else {
+            panic!("not an array");
+        };
+        let Value::Bytes(protected) = &items[0] else {
+            panic!("protected header is not a byte string");
+        };
+        let Value::Map(header) = serde_cbor::from_slice(protected).unwrap() else {
+            panic!("protected header is not a map");
+        };
+        assert_eq!(
+            header.get(&Value::Integer(HEADER_ALG)),
+            Some(&Value::Integer(ALG_ES256))
+        );
+        assert_eq!(
+            header.get(&Value::Integer(HEADER_KID)),
+            Some(&Value::Bytes(b"2025-1".to_vec()))
+        );
+    }
+
+    #[test]
+    fn opens_fixed_vector() {
+        let sign1 = open(&unhex(VECTOR)).unwrap();
+        assert!(verifies(&sign1, key().verifying_key()));
+
+        let payload = decode_claims(&sign1).unwrap();
+        assert_eq!(payload.sub, "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f");
+        assert_eq!((payload.name.as_str(), payload.t.as_str()), ("Max", "wi"));
+        assert_eq!((payload.iat, payload.exp), (1_750_000_000, 1_750_003_600));
+        assert_eq!(payload.kid.as_deref(), Some("2025-1"));
+        assert_eq!(payload.iss.as_deref(), Some("https://id.example.org"));
+        assert_eq!(
+            payload.jti.as_deref(),
+            Some("7d1e4b2c-9a3f-4e58-b6c1-2f0a9d8e7c65")
+        );
+        assert_eq!(payload.v, Some(2));
+        assert_eq!(payload.r, Some(vec!["vorstand".to_string()]));
+    }
+
+    #[test]
+    fn kid_is_covered_by_the_signature() {
+        let mut encoded = sign(&payload(), &key()).unwrap();
+        let kid = encoded.windows(6).position(|w| w == b"2025-1").unwrap();
+        encoded[kid + 5] = b'2';
+
+        let sign1 = open(&encoded).unwrap();
+        assert_eq!(sign1.kid.as_deref(), Some("2025-2"));
+        assert!(!verifies(&sign1, key().verifying_key()));
+    }
+
+    #[test]
+    fn rejects_key_of_another_kid() {
+        let sign1 = open(&sign(&payload(), &key()).unwrap()).unwrap();
+        let other = SigningKey::from_slice(&[8; 32]).unwrap();
+        assert!(!verifies(&sign1, other.verifying_key()));
+    }
+
+    #[test]
+    fn rejects_other_algorithms() {
+        let mut header = BTreeMap::new();
+        header.insert(Value::Integer(HEADER_ALG), Value::Integer(-35));
+        let sign1 = Value::Tag(
+            COSE_SIGN1_TAG,
+            Box::new(Value::Array(vec![
+                Value::Bytes(serde_cbor::to_vec(&Value::Map(header)).unwrap()),
+                Value::Map(BTreeMap::new()),
+                Value::Bytes(serde_cbor::to_vec(&claims_to_cbor(&payload())).unwrap()),
+                Value::Bytes(vec![0; 64]),
+            ])),
+        );
+        let result = open(&serde_cbor::to_vec(&sign1).unwrap());
+        assert!(matches!(result, Err(Error::UnsupportedAlgorithm(-35))));
+    }
+}
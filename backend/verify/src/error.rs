@@ -0,0 +1,79 @@
+use std::fmt;
+
+use crate::QrPayload;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The QR string is not valid base45.
+    Base45,
+    /// The decoded bytes are not a valid zlib stream.
+    Zlib(std::io::Error),
+    /// The decompressed data is too short to contain a signature.
+    TooShort(usize),
+    /// The payload could not be (de)serialized as CBOR.
+    Cbor(serde_cbor::Error),
+    /// The COSE_Sign1 structure is malformed.
+    Cose(&'static str),
+    /// The COSE header names an algorithm other than ES256.
+    UnsupportedAlgorithm(i128),
+    /// A required CWT claim is missing or has the wrong type.
+    MissingClaim(&'static str),
+    /// The payload uses a version this verifier does not understand.
+    UnsupportedVersion(u8),
+    /// No verifying key is known for the payload's key ID.
+    UnknownKey(String),
+    /// The signature does not match the verifying key.
+    InvalidSignature,
+    /// The signature is valid but `exp` lies in the past.
+    Expired(Box<QrPayload>),
+    /// The signature is valid but `iat` lies in the future.
+    NotYetValid(Box<QrPayload>),
+}
+
+impl Error {
+    /// The payload of a correctly signed code that failed the time checks.
+    pub fn payload(&self) -> Option<&QrPayload> {
+        match self {
+            Error::Expired(payload) | Error::NotYetValid(payload) => Some(payload),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Base45 => write!(f, "invalid base45 encoding"),
+            Error::Zlib(e) => write!(f, "invalid zlib stream: {e}"),
+            Error::TooShort(len) => {
+                write!(f, "data too short to contain a signature ({len} bytes)")
+            }
+            Error::Cbor(e) => write!(f, "invalid CBOR payload: {e}"),
+            Error::Cose(reason) => write!(f, "invalid COSE structure: {reason}"),
+            Error::UnsupportedAlgorithm(alg) => write!(f, "unsupported COSE algorithm {alg}"),
+            Error::MissingClaim(claim) => write!(f, "missing or invalid claim '{claim}'"),
+            Error::UnsupportedVersion(v) => write!(f, "unsupported payload version {v}"),
+            Error::UnknownKey(kid) if kid.is_empty() => write!(f, "no verifying key available"),
+            Error::UnknownKey(kid) => write!(f, "unknown signing key '{kid}'"),
+            Error::InvalidSignature => write!(f, "signature does not match the QR public key"),
+            Error::Expired(_) => write!(f, "QR code has expired"),
+            Error::NotYetValid(_) => write!(f, "QR code was issued in the future"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Zlib(e) => Some(e),
+            Error::Cbor(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_cbor::Error> for Error {
+    fn from(e: serde_cbor::Error) -> Self {
+        Error::Cbor(e)
+    }
+}
@@ -0,0 +1,388 @@
+//! Encoder and verifier for Neuland member ID QR codes.
+//!
+//! A QR code is the base45 encoding of a zlib-compressed, signed container.
+//! Two containers exist (see [`Format`]); the decoder detects them
+//! automatically:
+//!
+//! * **Legacy**: the CBOR-encoded [`QrPayload`] followed by a 64-byte P-256
+//!   ECDSA signature (`r || s`) over the CBOR bytes.
+//! * **COSE**: a tagged COSE_Sign1 structure signed with ES256 whose payload
+//!   is a CWT claims set (see [`cose`]).
+
+pub mod cose;
+mod error;
+pub mod revocation;
+
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use error::Error;
+pub use p256;
+
+/// Latest payload version understood by this crate. Codes without a version
+/// field are version 1; any version outside `1..=PAYLOAD_VERSION` is rejected.
+pub const PAYLOAD_VERSION: u8 = 2;
+
+/// Clock skew tolerated when checking `iat` and `exp`.
+pub const CLOCK_SKEW: u64 = 60 * 5;
+
+const SIGNATURE_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct QrPayload {
+    #[cfg_attr(
+        feature = "utoipa",
+        schema(example = "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f")
+    )]
+    pub sub: String,
+    #[cfg_attr(feature = "utoipa", schema(example = "Max"))]
+    pub name: String,
+    /// Credential type: `a` (app), `wi` (Apple Wallet), `wa` (Google Wallet)
+    /// or `t` (printed card).
+    #[cfg_attr(feature = "utoipa", schema(example = "a"))]
+    pub t: String,
+    #[cfg_attr(feature = "utoipa", schema(example = "1719322624"))]
+    pub iat: u64,
+    #[cfg_attr(feature = "utoipa", schema(example = "1720187424"))]
+    pub exp: u64,
+    /// ID of the signing key. Codes issued before key rotation carry none.
+    #[cfg_attr(feature = "utoipa", schema(example = "9c1f0e2a"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// Issuer of the code, if configured.
+    #[cfg_attr(
+        feature = "utoipa",
+        schema(example = "https://id.neuland-ingolstadt.de")
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// Random per-issuance identifier; absent in codes issued before the
+    /// issuance ledger existed.
+    #[cfg_attr(feature = "utoipa", schema(example = "5f0c2e9ab81d4a37"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Payload version; absent in version 1 codes.
+    #[cfg_attr(feature = "utoipa", schema(example = 2))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<u8>,
+    /// Compact role set derived from the member's SSO groups.
+    #[cfg_attr(feature = "utoipa", schema(example = json!(["vorstand"])))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r: Option<Vec<String>>,
+}
+
+impl QrPayload {
+    /// The payload version, treating a missing field as version 1. An
+    /// explicit `v` is returned as is, so `0` stays invalid.
+    pub fn version(&self) -> u8 {
+        self.v.unwrap_or(1)
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.r.iter().flatten().any(|r| r == role)
+    }
+}
+
+/// Signed container used inside the QR code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// CBOR payload with a raw signature appended.
+    #[default]
+    Legacy,
+    /// COSE_Sign1 with CWT claims.
+    Cose,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "legacy" => Ok(Format::Legacy),
+            "cose" => Ok(Format::Cose),
+            other => Err(format!("unknown QR format '{other}'")),
+        }
+    }
+}
+
+/// Looks up the verifying keys that may have signed a payload.
+pub trait KeyResolver {
+    /// Candidate keys for `kid`; `None` means the payload carries no key ID.
+    fn resolve(&self, kid: Option<&str>) -> Vec<VerifyingKey>;
+}
+
+impl KeyResolver for VerifyingKey {
+    fn resolve(&self, _kid: Option<&str>) -> Vec<VerifyingKey> {
+        vec![*self]
+    }
+}
+
+/// Signs `payload` in the legacy format and returns the base45 string to put
+/// into the QR code.
+pub fn encode(payload: &QrPayload, key: &SigningKey) -> Result<String, Error> {
+    encode_with(payload, key, Format::Legacy)
+}
+
+/// Signs `payload` using the given container `format`.
+pub fn encode_with(payload: &QrPayload, key: &SigningKey, format: Format) -> Result<String, Error> {
+    let combined = match format {
+        Format::Legacy => {
+            let cbor = serde_cbor::to_vec(payload)?;
+            let signature: Signature = key.sign(&cbor);
+
+            let mut combined = Vec::with_capacity(cbor.len() + SIGNATURE_LEN);
+            combined.extend_from_slice(&cbor);
+            combined.extend_from_slice(&signature.to_bytes());
+            combined
+        }
+        Format::Cose => cose::sign(payload, key)?,
+    };
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&combined).map_err(Error::Zlib)?;
+    let compressed = encoder.finish().map_err(Error::Zlib)?;
+
+    Ok(base45::encode(compressed))
+}
+
+/// Decodes `qr`, checks its signature and validates `iat`/`exp` against the
+/// current time. `keys` is either a single [`VerifyingKey`] or a key set
+/// resolving the payload's `kid`.
+pub fn decode_and_verify<K>(qr: &str, keys: &K) -> Result<QrPayload, Error>
+where
+    K: KeyResolver + ?Sized,
+{
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    decode_and_verify_at(qr, keys, now)
+}
+
+/// Like [`decode_and_verify`], but validates the timestamps against `now`
+/// (seconds since the Unix epoch).
+pub fn decode_and_verify_at<K>(qr: &str, keys: &K, now: u64) -> Result<QrPayload, Error>
+where
+    K: KeyResolver + ?Sized,
+{
+    let decompressed = decompress(qr)?;
+    let (payload, signed, sig_bytes) = if decompressed[0] == cose::COSE_SIGN1_PREFIX {
+        let sign1 = cose::open(&decompressed)?;
+        (
+            cose::decode_claims(&sign1)?,
+            sign1.to_be_signed,
+            sign1.signature,
+        )
+    } else {
+        let (cbor, sig_bytes) = decompressed.split_at(decompressed.len() - SIGNATURE_LEN);
+        let payload: QrPayload = serde_cbor::from_slice(cbor)?;
+        (payload, cbor.to_vec(), sig_bytes.to_vec())
+    };
+
+    verify_signature(keys, payload.kid.as_deref(), &signed, &sig_bytes)?;
+
+    if !(1..=PAYLOAD_VERSION).contains(&payload.version()) {
+        return Err(Error::UnsupportedVersion(payload.version()));
+    }
+
+    if payload.iat > now + CLOCK_SKEW {
+        return Err(Error::NotYetValid(Box::new(payload)));
+    }
+    if payload.exp + CLOCK_SKEW < now {
+        return Err(Error::Expired(Box::new(payload)));
+    }
+
+    Ok(payload)
+}
+
+/// Checks `signature` over `signed` against every key resolved for `kid`.
+pub(crate) fn verify_signature<K>(
+    keys: &K,
+    kid: Option<&str>,
+    signed: &[u8],
+    signature: &[u8],
+) -> Result<(), Error>
+where
+    K: KeyResolver + ?Sized,
+{
+    let candidates = keys.resolve(kid);
+    if candidates.is_empty() {
+        return Err(Error::UnknownKey(kid.unwrap_or_default().to_string()));
+    }
+    let signature = Signature::from_slice(signature).map_err(|_| Error::InvalidSignature)?;
+    if candidates
+        .iter()
+        .any(|key| key.verify(signed, &signature).is_ok())
+    {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+fn decompress(qr: &str) -> Result<Vec<u8>, Error> {
+    let compressed = base45::decode(qr.trim()).map_err(|_| Error::Base45)?;
+
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .map_err(Error::Zlib)?;
+
+    if decompressed.len() <= SIGNATURE_LEN {
+        return Err(Error::TooShort(decompressed.len()));
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_750_000_000;
+
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn payload() -> QrPayload {
+        QrPayload {
+            sub: "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f".into(),
+            name: "Max".into(),
+            t: "a".into(),
+            iat: NOW,
+            exp: NOW + 3600,
+            kid: None,
+            iss: None,
+            jti: None,
+            v: None,
+            r: None,
+        }
+    }
+
+    /// Compresses and encodes `container` like [`encode`] does.
+    fn pack(container: &[u8]) -> String {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(container).unwrap();
+        base45::encode(encoder.finish().unwrap())
+    }
+
+    #[test]
+    fn round_trips_legacy_codes() {
+        let qr = encode(&payload(), &key()).unwrap();
+        let decoded = decode_and_verify_at(&qr, key().verifying_key(), NOW).unwrap();
+        assert_eq!(decoded.sub, payload().sub);
+        assert_eq!(decoded.name, "Max");
+        assert_eq!(decoded.t, "a");
+        assert_eq!((decoded.iat, decoded.exp), (NOW, NOW + 3600));
+    }
+
+    #[test]
+    fn rejects_tampered_payload_and_signature() {
+        let qr = encode(&payload(), &key()).unwrap();
+        let container = decompress(&qr).unwrap();
+
+        let mut payload_tampered = container.clone();
+        let name = payload_tampered
+            .windows(3)
+            .position(|w| w == b"Max")
+            .unwrap();
+        payload_tampered[name] = b'M' + 1;
+        let result = decode_and_verify_at(&pack(&payload_tampered), key().verifying_key(), NOW);
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+
+        let mut signature_tampered = container;
+        *signature_tampered.last_mut().unwrap() ^= 1;
+        let result = decode_and_verify_at(&pack(&signature_tampered), key().verifying_key(), NOW);
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+
+        let other = SigningKey::from_slice(&[8; 32]).unwrap();
+        let result = decode_and_verify_at(&qr, other.verifying_key(), NOW);
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn timestamps_tolerate_clock_skew() {
+        let qr = encode(&payload(), &key()).unwrap();
+        let at = |now| decode_and_verify_at(&qr, key().verifying_key(), now);
+
+        assert!(at(NOW - CLOCK_SKEW).is_ok());
+        assert!(matches!(
+            at(NOW - CLOCK_SKEW - 1),
+            Err(Error::NotYetValid(_))
+        ));
+        assert!(at(NOW + 3600 + CLOCK_SKEW).is_ok());
+        assert!(matches!(
+            at(NOW + 3600 + CLOCK_SKEW + 1),
+            Err(Error::Expired(_))
+        ));
+    }
+
+    /// Key set holding the current key and one rotated out of signing.
+    struct Ring(Vec<(&'static str, VerifyingKey)>);
+
+    impl KeyResolver for Ring {
+        fn resolve(&self, kid: Option<&str>) -> Vec<VerifyingKey> {
+            self.0
+                .iter()
+                .filter(|(id, _)| kid.is_none_or(|kid| kid == *id))
+                .map(|(_, key)| *key)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn resolves_rotated_keys_by_kid() {
+        let old = SigningKey::from_slice(&[8; 32]).unwrap();
+        let ring = Ring(vec![
+            ("new", *key().verifying_key()),
+            ("old", *old.verifying_key()),
+        ]);
+        let signed = |key: &SigningKey, kid: Option<&str>| {
+            let payload = QrPayload {
+                kid: kid.map(str::to_string),
+                ..payload()
+            };
+            encode(&payload, key).unwrap()
+        };
+
+        let decoded = decode_and_verify_at(&signed(&old, Some("old")), &ring, NOW).unwrap();
+        assert_eq!(decoded.kid.as_deref(), Some("old"));
+        assert!(decode_and_verify_at(&signed(&key(), Some("new")), &ring, NOW).is_ok());
+        // Codes from before the rotation carry no kid and try every key.
+        assert!(decode_and_verify_at(&signed(&old, None), &ring, NOW).is_ok());
+
+        let result = decode_and_verify_at(&signed(&old, Some("gone")), &ring, NOW);
+        assert!(matches!(result, Err(Error::UnknownKey(kid)) if kid == "gone"));
+        let result = decode_and_verify_at(&signed(&old, Some("new")), &ring, NOW);
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn rejects_unsupported_versions_after_the_signature() {
+        let versioned = |v| QrPayload {
+            v: Some(v),
+            ..payload()
+        };
+        for v in 1..=PAYLOAD_VERSION {
+            let qr = encode(&versioned(v), &key()).unwrap();
+            assert!(decode_and_verify_at(&qr, key().verifying_key(), NOW).is_ok());
+        }
+        for v in [0, PAYLOAD_VERSION + 1] {
+            let qr = encode(&versioned(v), &key()).unwrap();
+            let result = decode_and_verify_at(&qr, key().verifying_key(), NOW);
+            assert!(matches!(result, Err(Error::UnsupportedVersion(found)) if found == v));
+
+            // A forged code learns nothing about which versions are accepted.
+            let other = SigningKey::from_slice(&[8; 32]).unwrap();
+            let forged = encode(&versioned(v), &other).unwrap();
+            let result = decode_and_verify_at(&forged, key().verifying_key(), NOW);
+            assert!(matches!(result, Err(Error::InvalidSignature)));
+        }
+    }
+}
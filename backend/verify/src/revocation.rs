@@ -0,0 +1,125 @@
+//! Signed revocation lists that offline scanners can sync.
+//!
+//! The list is CBOR-encoded and wrapped into a COSE_Sign1 structure signed
+//! with the same keys as the QR codes (see [`crate::cose`]).
+
+use p256::ecdsa::SigningKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, KeyResolver, QrPayload, cose};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevocationList {
+    /// When the list was generated (seconds since the Unix epoch).
+    pub iat: u64,
+    pub entries: Vec<RevokedEntry>,
+    /// Individually revoked issuances (`jti`) that have not expired yet.
+    #[serde(default)]
+    pub jtis: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevokedEntry {
+    pub sub: String,
+    /// Codes for `sub` issued at or before this instant are revoked; codes
+    /// issued afterwards are unaffected.
+    pub revoked_at: u64,
+}
+
+impl RevocationList {
+    pub fn is_revoked(&self, payload: &QrPayload) -> bool {
+        let jti_revoked = payload
+            .jti
+            .as_ref()
+            .is_some_and(|jti| self.jtis.contains(jti));
+        jti_revoked
+            || self
+                .entries
+                .iter()
+                .any(|entry| entry.sub == payload.sub && payload.iat <= entry.revoked_at)
+    }
+
+    /// Signs the list and returns the COSE_Sign1 bytes.
+    pub fn sign(&self, kid: Option<&str>, key: &SigningKey) -> Result<Vec<u8>, Error> {
+        cose::sign_bytes(&serde_cbor::to_vec(self)?, kid, key)
+    }
+
+    /// Checks the signature of a downloaded list and decodes it.
+    pub fn verify<K>(data: &[u8], keys: &K) -> Result<Self, Error>
+    where
+        K: KeyResolver + ?Sized,
+    {
+        Ok(serde_cbor::from_slice(&cose::verify_bytes(data, keys)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SigningKey {
+        SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn list() -> RevocationList {
+        RevocationList {
+            iat: 1_750_000_000,
+            entries: vec![RevokedEntry {
+                sub: "3f2c9a1e-5b7d-4c1a-9e8f-0a1b2c3d4e5f".into(),
+                revoked_at: 1_749_000_000,
+            }],
+            jtis: vec!["7d1e4b2c-9a3f-4e58-b6c1-2f0a9d8e7c65".into()],
+        }
+    }
+
+    #[test]
+    fn round_trips_signed_lists() {
+        let signed = list().sign(Some("2025-1"), &key()).unwrap();
+        let verified = RevocationList::verify(&signed, key().verifying_key()).unwrap();
+        assert_eq!(verified.iat, 1_750_000_000);
+        assert_eq!(verified.entries.len(), 1);
+        assert_eq!(verified.entries[0].sub, list().entries[0].sub);
+        assert_eq!(verified.entries[0].revoked_at, 1_749_000_000);
+        assert_eq!(verified.jtis, list().jtis);
+    }
+
+    #[test]
+    fn rejects_tampered_lists() {
+        let signed = list().sign(None, &key()).unwrap();
+
+        let mut tampered = signed.clone();
+        let sub = tampered.windows(8).position(|w| w == b"3f2c9a1e").unwrap();
+        tampered[sub] = b'4';
+        let result = RevocationList::verify(&tampered, key().verifying_key());
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+
+        let other = SigningKey::from_slice(&[8; 32]).unwrap();
+        let result = RevocationList::verify(&signed, other.verifying_key());
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn revokes_members_and_single_issuances() {
+        let list = list();
+        let code = |sub: &str, iat, jti: Option<&str>| QrPayload {
+            sub: sub.into(),
+            name: "Max".into(),
+            t: "a".into(),
+            iat,
+            exp: iat + 3600,
+            kid: None,
+            iss: None,
+            jti: jti.map(str::to_string),
+            v: None,
+            r: None,
+        };
+        let sub = list.entries[0].sub.clone();
+        assert!(list.is_revoked(&code(&sub, 1_749_000_000, None)));
+        assert!(!list.is_revoked(&code(&sub, 1_749_000_001, None)));
+        assert!(!list.is_revoked(&code("someone-else", 1_748_000_000, None)));
+
+        let jti = list.jtis[0].clone();
+        assert!(list.is_revoked(&code("someone-else", 1_750_000_000, Some(&jti))));
+        assert!(!list.is_revoked(&code("someone-else", 1_750_000_000, Some("other"))));
+    }
+}
@@ -63,7 +63,9 @@ export function SuccessOverlay({
 									? 'Apple Wallet Pass'
 									: result.payload.type === QRType.ANDROID_WALLET
 										? 'Android Wallet Pass'
-										: result.payload.type}
+										: result.payload.type === QRType.PRINTED_CARD
+											? 'Printed Card'
+											: result.payload.type}
 						</p>
 					</div>
 				)}
@@ -124,7 +126,9 @@ export function DuplicateOverlay({
 										? 'Apple Wallet Pass'
 										: warning.result.payload.type === QRType.ANDROID_WALLET
 											? 'Android Wallet Pass'
-											: warning.result.payload.type}
+											: warning.result.payload.type === QRType.PRINTED_CARD
+												? 'Printed Card'
+												: warning.result.payload.type}
 							</span>
 						</p>
 						<p className="text-white/80 text-xs mt-1">
@@ -3,12 +3,14 @@
 import {
 	CheckCircle,
 	Clock,
+	CreditCard,
 	Info,
 	ShieldX,
 	Smartphone,
 	Ticket,
 	TriangleAlert,
-	User
+	User,
+	Users
 } from 'lucide-react'
 import { Badge } from '@/components/ui/badge'
 import { Card, CardContent } from '@/components/ui/card'
@@ -68,7 +70,10 @@ export function ResultCard({ result, duplicateWarning }: Props) {
 															: duplicateWarning.result.payload.type ===
 																	QRType.ANDROID_WALLET
 																? 'Android Wallet Pass'
-																: duplicateWarning.result.payload.type
+																: duplicateWarning.result.payload.type ===
+																		QRType.PRINTED_CARD
+																	? 'Printed Card'
+																	: duplicateWarning.result.payload.type
 												} at ${new Date(duplicateWarning.timestamp).toLocaleTimeString()}`
 											: 'Neuland ID signature is valid'}
 									</p>
@@ -231,6 +236,8 @@ export function ResultCard({ result, duplicateWarning }: Props) {
 												<Ticket className="h-4 w-4 text-gray-500" />
 											) : result.payload.type === QRType.ANDROID_WALLET ? (
 												<Smartphone className="h-4 w-4 text-gray-500" />
+											) : result.payload.type === QRType.PRINTED_CARD ? (
+												<CreditCard className="h-4 w-4 text-gray-500" />
 											) : (
 												<ShieldX className="h-4 w-4 text-gray-500" />
 											)}
@@ -245,9 +252,29 @@ export function ResultCard({ result, duplicateWarning }: Props) {
 													? 'Apple Wallet Pass'
 													: result.payload.type === QRType.ANDROID_WALLET
 														? 'Android Wallet Pass'
-														: result.payload.type}
+														: result.payload.type === QRType.PRINTED_CARD
+															? 'Printed Card'
+															: result.payload.type}
 										</p>
 									</div>
+
+									{result.payload.roles && result.payload.roles.length > 0 && (
+										<div className="space-y-2">
+											<div className="flex items-center gap-2">
+												<Users className="h-4 w-4 text-gray-500" />
+												<span className="text-sm font-medium text-gray-700 dark:text-gray-300">
+													Roles
+												</span>
+											</div>
+											<div className="flex flex-wrap gap-1 pl-6">
+												{result.payload.roles.map((role) => (
+													<Badge key={role} variant="secondary">
+														{role}
+													</Badge>
+												))}
+											</div>
+										</div>
+									)}
 								</div>
 							</div>
 
@@ -8,6 +8,8 @@ export function getQRTypeDisplayName(type: string): string {
 			return 'Apple Wallet Pass'
 		case QRType.ANDROID_WALLET:
 			return 'Android Wallet Pass'
+		case QRType.PRINTED_CARD:
+			return 'Printed Card'
 		default:
 			return type
 	}
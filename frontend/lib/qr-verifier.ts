@@ -4,12 +4,20 @@ export interface QRPayload {
 	iat: number
 	exp: number
 	type: string
+	kid?: string
+	jti?: string
+	version: number
+	roles?: string[]
 }
 
+// Latest payload version this verifier understands; codes without one are v1
+export const SUPPORTED_PAYLOAD_VERSION = 2
+
 export enum QRType {
 	APP = 'app',
 	APPLE_WALLET = 'apple_wallet',
-	ANDROID_WALLET = 'android_wallet'
+	ANDROID_WALLET = 'android_wallet',
+	PRINTED_CARD = 'printed_card'
 }
 
 export interface VerificationResult {
@@ -34,30 +42,34 @@ export interface VerificationSettings {
 }
 
 import { decode as base45DecodeLib } from 'base45'
-import { decode as cborDecode } from 'cborg'
+import { decode as cborDecode, encode as cborEncode } from 'cborg'
 import { inflate } from 'pako'
 
-let cachedPublicKey: string | null = null
-let publicKeyPromise: Promise<string> | null = null
+interface JwkSet {
+	keys: (JsonWebKey & { kid: string })[]
+}
+
+let cachedPublicKeys: Map<string, CryptoKey> | null = null
+let publicKeyPromise: Promise<Map<string, CryptoKey>> | null = null
 let publicKeyError: string | null = null
 
 export function clearPublicKeyCache(): void {
-	cachedPublicKey = null
+	cachedPublicKeys = null
 	publicKeyPromise = null
 	publicKeyError = null
 }
 
 export function isPublicKeyAvailable(): boolean {
-	return cachedPublicKey !== null && publicKeyError === null
+	return cachedPublicKeys !== null && publicKeyError === null
 }
 
 export function getPublicKeyError(): string | null {
 	return publicKeyError
 }
 
-async function fetchPublicKey(): Promise<string> {
-	if (cachedPublicKey) {
-		return cachedPublicKey
+async function fetchPublicKeys(): Promise<Map<string, CryptoKey>> {
+	if (cachedPublicKeys) {
+		return cachedPublicKeys
 	}
 
 	if (publicKeyPromise) {
@@ -66,10 +78,10 @@ async function fetchPublicKey(): Promise<string> {
 
 	publicKeyPromise = (async () => {
 		try {
-			const response = await fetch('/api/public-key', {
+			const response = await fetch('/api/.well-known/jwks.json', {
 				method: 'GET',
 				headers: {
-					Accept: 'text/plain'
+					Accept: 'application/jwk-set+json'
 				}
 			})
 
@@ -77,16 +89,30 @@ async function fetchPublicKey(): Promise<string> {
 				throw new Error(response.statusText)
 			}
 
-			const publicKey = await response.text()
-			const trimmedKey = publicKey.trim()
+			const jwks = (await response.json()) as JwkSet
+			const keys = new Map<string, CryptoKey>()
+
+			for (const jwk of jwks.keys ?? []) {
+				if (jwk.kty !== 'EC' || jwk.crv !== 'P-256') {
+					continue
+				}
+				const cryptoKey = await crypto.subtle.importKey(
+					'jwk',
+					jwk,
+					{ name: 'ECDSA', namedCurve: 'P-256' },
+					false,
+					['verify']
+				)
+				keys.set(jwk.kid, cryptoKey)
+			}
 
-			if (!/^[0-9a-fA-F]+$/.test(trimmedKey)) {
-				throw new Error('Invalid public key format: not a valid hex string')
+			if (keys.size === 0) {
+				throw new Error('No public keys available')
 			}
 
-			cachedPublicKey = trimmedKey
+			cachedPublicKeys = keys
 			publicKeyError = null
-			return trimmedKey
+			return keys
 		} catch (error) {
 			publicKeyPromise = null
 			const errorMessage = `Failed to fetch public key: ${error instanceof Error ? error.message : 'Unknown error'}`
@@ -107,7 +133,7 @@ export async function initializePublicKey(): Promise<void> {
 
 	initializationPromise = (async () => {
 		try {
-			await fetchPublicKey()
+			await fetchPublicKeys()
 			console.log('Successfully initialized public key from backend')
 		} catch (error) {
 			console.error('Failed to initialize public key:', error)
@@ -153,16 +179,10 @@ export async function verifyQRCode(
 
 		const decompressed = zlibDecompress(base45Decoded)
 
-		if (decompressed.length < 64) {
-			throw new Error(
-				`Data too short to contain signature. Length: ${decompressed.length}, required: >= 64`
-			)
-		}
-
-		const cborData = decompressed.slice(0, -64)
-		const signatureBytes = decompressed.slice(-64)
-
-		const payload = await parseCBOR(cborData)
+		const { cborData, signedData, signatureBytes, payload } =
+			decompressed[0] === COSE_SIGN1_PREFIX
+				? parseCoseSign1(decompressed)
+				: await parseLegacy(decompressed)
 		console.log('Parsed payload:', payload)
 
 		// Apply validation settings
@@ -251,7 +271,11 @@ export async function verifyQRCode(
 			}
 		}
 
-		const signatureValid = await verifySignature(cborData, signatureBytes)
+		const signatureValid = await verifySignature(
+			signedData,
+			signatureBytes,
+			payload.kid
+		)
 
 		const now = Math.floor(Date.now() / 1000)
 		const isExpired = payload.exp < now
@@ -289,28 +313,6 @@ export async function verifyQRCode(
 	}
 }
 
-function hexToBytes(hex: string): Uint8Array {
-	const clean = hex.startsWith('0x') ? hex.slice(2) : hex
-
-	if (clean.length % 2 !== 0) {
-		throw new Error(`Invalid hex string length: ${clean.length} (must be even)`)
-	}
-
-	const arr = new Uint8Array(clean.length / 2)
-	for (let i = 0; i < arr.length; i++) {
-		const hexPair = clean.slice(i * 2, i * 2 + 2)
-		const byte = parseInt(hexPair, 16)
-		if (Number.isNaN(byte)) {
-			throw new Error(
-				`Invalid hex characters at position ${i * 2}: "${hexPair}"`
-			)
-		}
-		arr[i] = byte
-	}
-
-	return arr
-}
-
 function zlibDecompress(data: Uint8Array): Uint8Array {
 	try {
 		if (!data || data.length === 0) {
@@ -333,35 +335,37 @@ function zlibDecompress(data: Uint8Array): Uint8Array {
 
 async function verifySignature(
 	data: Uint8Array,
-	signature: Uint8Array
+	signature: Uint8Array,
+	kid?: string
 ): Promise<boolean> {
 	try {
 		if (signature.length !== 64) {
 			return false
 		}
 
-		if (!cachedPublicKey) {
+		if (!cachedPublicKeys) {
 			throw new Error('Public key not available')
 		}
 
-		const keyBytes = hexToBytes(cachedPublicKey)
-
-		const cryptoKey = await crypto.subtle.importKey(
-			'raw',
-			keyBytes,
-			{ name: 'ECDSA', namedCurve: 'P-256' },
-			false,
-			['verify']
-		)
+		// Codes issued before key rotation carry no kid, so try every key
+		const candidates = kid
+			? [cachedPublicKeys.get(kid)].filter((k): k is CryptoKey => !!k)
+			: [...cachedPublicKeys.values()]
+
+		for (const cryptoKey of candidates) {
+			const result = await crypto.subtle.verify(
+				{ name: 'ECDSA', hash: 'SHA-256' },
+				cryptoKey,
+				signature,
+				data
+			)
 
-		const result = await crypto.subtle.verify(
-			{ name: 'ECDSA', hash: 'SHA-256' },
-			cryptoKey,
-			signature,
-			data
-		)
+			if (result) {
+				return true
+			}
+		}
 
-		return result
+		return false
 	} catch (error) {
 		throw new Error(
 			`Signature verification failed: ${error instanceof Error ? error.message : 'Unknown error'}`
@@ -369,52 +373,179 @@ async function verifySignature(
 	}
 }
 
-async function parseCBOR(data: Uint8Array): Promise<QRPayload> {
-	try {
-		const decoded = cborDecode(data)
+// COSE_Sign1 is CBOR tag 18 (RFC 9052), so tagged structures start with 0xd2
+const COSE_SIGN1_TAG = 18
+const COSE_SIGN1_PREFIX = 0xd2
+const COSE_HEADER_ALG = 1
+const COSE_HEADER_KID = 4
+const COSE_ALG_ES256 = -7
+const CWT_CLAIM_SUB = 2
+const CWT_CLAIM_EXP = 4
+const CWT_CLAIM_IAT = 6
+const CWT_CLAIM_CTI = 7
+
+interface SignedContainer {
+	cborData: Uint8Array
+	signedData: Uint8Array
+	signatureBytes: Uint8Array
+	payload: QRPayload
+}
 
-		if (!decoded || typeof decoded !== 'object') {
-			throw new Error('Invalid CBOR payload - not an object')
-		}
+async function parseLegacy(decompressed: Uint8Array): Promise<SignedContainer> {
+	if (decompressed.length < 64) {
+		throw new Error(
+			`Data too short to contain signature. Length: ${decompressed.length}, required: >= 64`
+		)
+	}
 
-		const decodedObj = decoded as Record<string, unknown>
-		const { sub, name, iat, exp, t } = decodedObj
+	const cborData = decompressed.slice(0, -64)
+	const signatureBytes = decompressed.slice(-64)
+	const payload = await parseCBOR(cborData)
 
-		if (!sub || typeof sub !== 'string') {
-			throw new Error("Missing or invalid 'sub' field")
+	return { cborData, signedData: cborData, signatureBytes, payload }
+}
+
+function parseCoseSign1(data: Uint8Array): SignedContainer {
+	try {
+		const tags: ((inner: unknown) => unknown)[] = []
+		tags[COSE_SIGN1_TAG] = (inner) => inner
+		const sign1 = cborDecode(data, { tags })
+
+		if (!Array.isArray(sign1) || sign1.length !== 4) {
+			throw new Error('COSE_Sign1 must be an array of four elements')
 		}
-		if (!name || typeof name !== 'string') {
-			throw new Error("Missing or invalid 'name' field")
+
+		const [protectedHeader, , claimsData, signatureBytes] = sign1
+		if (
+			!(protectedHeader instanceof Uint8Array) ||
+			!(claimsData instanceof Uint8Array) ||
+			!(signatureBytes instanceof Uint8Array)
+		) {
+			throw new Error('COSE_Sign1 elements have the wrong type')
 		}
-		if (typeof iat !== 'number') {
-			throw new Error("Missing or invalid 'iat' field")
+
+		const header = cborDecode(protectedHeader, { useMaps: true })
+		if (!(header instanceof Map)) {
+			throw new Error('Protected header is not a map')
 		}
-		if (typeof exp !== 'number') {
-			throw new Error("Missing or invalid 'exp' field")
+		if (header.get(COSE_HEADER_ALG) !== COSE_ALG_ES256) {
+			throw new Error(
+				`Unsupported COSE algorithm: ${header.get(COSE_HEADER_ALG)}`
+			)
 		}
-		if (!t || typeof t !== 'string') {
-			throw new Error("Missing or invalid 't' field")
+		const kidBytes = header.get(COSE_HEADER_KID)
+		const kid =
+			kidBytes instanceof Uint8Array
+				? new TextDecoder().decode(kidBytes)
+				: undefined
+
+		const claims = cborDecode(claimsData, { useMaps: true })
+		if (!(claims instanceof Map)) {
+			throw new Error('CWT claims are not a map')
 		}
 
-		let type: string
-		switch (t) {
-			case 'a':
-				type = QRType.APP
-				break
-			case 'wi':
-				type = QRType.APPLE_WALLET
-				break
-			case 'wa':
-				type = QRType.ANDROID_WALLET
-				break
-			default:
-				throw new Error(`Invalid type code: ${t}`)
+		const cti = claims.get(CWT_CLAIM_CTI)
+		const payload = toPayload({
+			sub: claims.get(CWT_CLAIM_SUB),
+			name: claims.get('name'),
+			iat: claims.get(CWT_CLAIM_IAT),
+			exp: claims.get(CWT_CLAIM_EXP),
+			t: claims.get('t'),
+			kid,
+			jti:
+				cti instanceof Uint8Array ? new TextDecoder().decode(cti) : undefined,
+			v: claims.get('v'),
+			r: claims.get('r')
+		})
+
+		// Sig_structure for COSE_Sign1 with empty external AAD
+		const signedData = cborEncode([
+			'Signature1',
+			protectedHeader,
+			new Uint8Array(0),
+			claimsData
+		])
+
+		return { cborData: claimsData, signedData, signatureBytes, payload }
+	} catch (error) {
+		throw new Error(
+			`COSE parsing failed: ${error instanceof Error ? error.message : 'Unknown error'}`
+		)
+	}
+}
+
+async function parseCBOR(data: Uint8Array): Promise<QRPayload> {
+	try {
+		const decoded = cborDecode(data)
+
+		if (!decoded || typeof decoded !== 'object') {
+			throw new Error('Invalid CBOR payload - not an object')
 		}
 
-		return { sub, name, iat, exp, type }
+		return toPayload(decoded as Record<string, unknown>)
 	} catch (error) {
 		throw new Error(
 			`CBOR parsing failed: ${error instanceof Error ? error.message : 'Unknown error'}`
 		)
 	}
 }
+
+function toPayload(decodedObj: Record<string, unknown>): QRPayload {
+	const { sub, name, iat, exp, t, kid, jti, v, r } = decodedObj
+
+	const version = v === undefined ? 1 : v
+	if (typeof version !== 'number' || !Number.isInteger(version)) {
+		throw new Error("Invalid 'v' field")
+	}
+	if (version > SUPPORTED_PAYLOAD_VERSION) {
+		throw new Error(`Unsupported payload version: ${version}`)
+	}
+
+	if (!sub || typeof sub !== 'string') {
+		throw new Error("Missing or invalid 'sub' field")
+	}
+	if (!name || typeof name !== 'string') {
+		throw new Error("Missing or invalid 'name' field")
+	}
+	if (typeof iat !== 'number') {
+		throw new Error("Missing or invalid 'iat' field")
+	}
+	if (typeof exp !== 'number') {
+		throw new Error("Missing or invalid 'exp' field")
+	}
+	if (!t || typeof t !== 'string') {
+		throw new Error("Missing or invalid 't' field")
+	}
+	if (kid !== undefined && typeof kid !== 'string') {
+		throw new Error("Invalid 'kid' field")
+	}
+	if (jti !== undefined && typeof jti !== 'string') {
+		throw new Error("Invalid 'jti' field")
+	}
+	if (
+		r !== undefined &&
+		(!Array.isArray(r) || !r.every((role) => typeof role === 'string'))
+	) {
+		throw new Error("Invalid 'r' field")
+	}
+
+	let type: string
+	switch (t) {
+		case 'a':
+			type = QRType.APP
+			break
+		case 'wi':
+			type = QRType.APPLE_WALLET
+			break
+		case 'wa':
+			type = QRType.ANDROID_WALLET
+			break
+		case 't':
+			type = QRType.PRINTED_CARD
+			break
+		default:
+			throw new Error(`Invalid type code: ${t}`)
+	}
+
+	return { sub, name, iat, exp, type, kid, jti, version, roles: r }
+}